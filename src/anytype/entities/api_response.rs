@@ -1,10 +1,40 @@
-use chrono::{DateTime, Datelike, Local};
+use crate::config::StatusConfig;
+
+use chrono::{DateTime, TimeZone};
 use serde::{Deserialize, Serialize};
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ApiResponse {
     pub data: Vec<AnytypeObject>,
+    pub pagination: Option<Pagination>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ObjectResponse {
+    pub object: AnytypeObject,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MembersResponse {
+    pub data: Vec<Member>,
+    pub pagination: Option<Pagination>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Member {
+    pub id: String,
+    pub name: Option<String>,
+    pub global_name: Option<String>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Pagination {
+    pub has_more: bool,
+    pub limit: u64,
+    pub offset: u64,
+    pub total: u64,
 }
 
 #[allow(dead_code)]
@@ -48,76 +78,58 @@ impl AnytypeObject {
             .unwrap_or_default()
     }
 
-    /// Get the name of the month in Russian
-    fn month_name(local_time: DateTime<Local>) -> String {
-        let month_name = match local_time.month() {
-            1 => "января",
-            2 => "февраля",
-            3 => "марта",
-            4 => "апреля",
-            5 => "мая",
-            6 => "июня",
-            7 => "июля",
-            8 => "августа",
-            9 => "сентября",
-            10 => "октября",
-            11 => "ноября",
-            12 => "декабря",
-            _ => "нихуя себе, номер месяца не входит в 1–12!?",
+    /// Get the names of the selected options of the select or multi-select property
+    pub fn select_values(properties: &[Property], key: &str) -> Vec<String> {
+        let Some(property) = properties.iter().find(|p| p.key == key) else {
+            return Vec::new();
         };
 
-        month_name.to_string()
+        property
+            .select
+            .iter()
+            .chain(property.multi_select.iter().flatten())
+            .map(|s| s.name.clone())
+            .collect()
     }
 
-    pub fn creation_date(&self) -> String {
-        let raw = self
-            .properties
+    /// Get the name of the selected option of the select property
+    pub fn select(&self, key: &str) -> Option<String> {
+        self.properties
+            .iter()
+            .find(|p| p.key == key)
+            .and_then(|p| p.select.as_ref())
+            .map(|s| s.name.clone())
+    }
+
+    /// Check if the object has the "done" status from config
+    pub fn is_done(&self, status_config: Option<&StatusConfig>) -> bool {
+        status_config.is_some_and(|c| self.select(&c.property).as_deref() == Some(c.done.as_str()))
+    }
+
+    /// Get the creation date as it is stored in Anytype (RFC 3339)
+    pub fn raw_creation_date(&self) -> Option<String> {
+        self.properties
             .iter()
             .find(|p| p.key == "created_date")
-            .and_then(|p| p.date.as_deref());
-
-        // Date and time formatting and converting it from UTC to local
-        match raw {
-            Some(date_str) => match DateTime::parse_from_rfc3339(date_str) {
-                Ok(dt) => {
-                    let local_time = dt.with_timezone(&Local);
-                    let day = local_time.day();
-                    let year = local_time.year();
-                    let month = Self::month_name(local_time);
-
-                    format!(
-                        "{day} {month} {year}, {time}",
-                        time = local_time.format("%H:%M")
-                    )
-                }
-                Err(_) => format!("Invalid date format: {date_str}"),
-            },
-            None => "<no creation date>".to_string(),
-        }
+            .and_then(|p| p.date.clone())
     }
 
-    pub fn due_date(&self) -> String {
-        let raw = self
-            .properties
+    /// Get the due date as it is stored in Anytype (RFC 3339)
+    pub fn raw_due_date(&self) -> Option<String> {
+        self.properties
             .iter()
             .find(|p| p.key == "due_date")
-            .and_then(|p| p.date.as_deref());
-
-        // Date formatting
-        match raw {
-            Some(date_str) => match DateTime::parse_from_rfc3339(date_str) {
-                Ok(dt) => {
-                    let local_time = dt.with_timezone(&Local);
-                    let day = local_time.day();
-                    let year = local_time.year();
-                    let month = Self::month_name(local_time);
-
-                    format!("{day} {month} {year}")
-                }
-                Err(_) => format!("Invalid date format: {date_str}"),
-            },
-            None => "<no deadline>".to_string(),
-        }
+            .and_then(|p| p.date.clone())
+    }
+
+    /// Check if the deadline has passed. Date-only deadlines are stored as midnight,
+    /// so the calendar days are compared in the time zone of `now`
+    pub fn is_overdue<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> bool {
+        self.raw_due_date()
+            .and_then(|d| DateTime::parse_from_rfc3339(&d).ok())
+            .is_some_and(|due_date| {
+                due_date.with_timezone(&now.timezone()).date_naive() < now.date_naive()
+            })
     }
 }
 
@@ -1,16 +1,66 @@
-use serde::{Deserialize, Serialize};
+use crate::{anytype::entities::api_response::AnytypeObject, config::StatusConfig};
+
+use serde::{Deserialize, Deserializer, Serialize};
 use std::{collections::HashMap, time::SystemTime};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CachedObject {
+    // Name is kept to notify about the object after it is deleted
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub type_key: Option<String>,
     pub notify: bool,
     pub notified: bool,
     pub proposed_by: Vec<String>,
     pub assignee: Vec<String>,
     pub notified_in_time: SystemTime,
+    // None if the cache was created by a version that didn't store the due date
+    #[serde(default, deserialize_with = "deserialize_cached_value")]
+    pub due_date: Option<Option<String>>,
+    // When the last overdue notification was sent and how many were sent in a row
+    #[serde(default)]
+    pub overdue_notified_in_time: Option<SystemTime>,
+    #[serde(default)]
+    pub overdue_count: u64,
+    // None if the status wasn't cached yet
+    #[serde(default, deserialize_with = "deserialize_cached_value")]
+    pub status: Option<Option<String>>,
+}
+
+impl CachedObject {
+    /// Cache the actual state of the object
+    pub fn new(
+        object: &AnytypeObject,
+        notified: bool,
+        status_config: Option<&StatusConfig>,
+    ) -> Self {
+        Self {
+            name: object.name.clone(),
+            type_key: object.type_field.as_ref().map(|t| t.key.clone()),
+            notify: object.is_notify_enabled(),
+            notified,
+            proposed_by: object.proposed_by(),
+            assignee: object.assignee(),
+            notified_in_time: SystemTime::now(),
+            due_date: Some(object.raw_due_date()),
+            overdue_notified_in_time: None,
+            overdue_count: 0,
+            status: status_config.map(|c| object.select(&c.property)),
+        }
+    }
+}
+
+/// Distinguish a missing field (not cached yet) from a cached null value
+fn deserialize_cached_value<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct AnytypeCache {
     pub objects: HashMap<String, CachedObject>,
 }
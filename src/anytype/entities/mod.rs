@@ -1,3 +1,4 @@
 pub mod api_response;
 pub mod cache;
 pub mod notification;
+pub mod search;
@@ -1,46 +1,123 @@
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, error::Error};
+use std::collections::{HashMap, HashSet};
 
-use crate::anytype::entities::api_response::AnytypeObject;
+use crate::anytype::entities::{
+    api_response::{AnytypeObject, Property},
+    cache::CachedObject,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum NotificationType {
     New,
     Unassigned,
     UpcomingDeadline,
+    AssigneeChanged {
+        added: Vec<String>,
+        removed: Vec<String>,
+    },
+    DeadlineChanged {
+        old: Option<String>,
+        new: Option<String>,
+    },
+    StatusChanged {
+        old: Option<String>,
+        new: Option<String>,
+        // The new status means the object is done
+        done: bool,
+    },
+    Archived,
+    Deleted,
+    Overdue {
+        // How many overdue notifications were sent in a row, including this one
+        missed: u64,
+        // Mention the members who proposed the task
+        escalate: bool,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NotificationObject {
     pub id: String,
+    pub space_id: String,
     pub name: String,
-    pub snippet: String,
-    pub creation_date: String,
-    pub due_date: String,
+    pub snippet: Option<String>,
+    // Dates as they are stored in Anytype (RFC 3339), they are formatted with the locale
+    pub creation_date: Option<String>,
+    pub due_date: Option<String>,
     pub proposed_by: Vec<String>,
     pub assignee: Vec<String>,
     pub notification_type: NotificationType,
+    pub type_key: Option<String>,
+    pub properties: Vec<Property>,
 }
 
 impl NotificationObject {
-    pub fn new(
-        object: &AnytypeObject,
-        notification_type: NotificationType,
-    ) -> Result<Self, Box<dyn Error>> {
-        Ok(Self {
+    pub fn new(object: &AnytypeObject, notification_type: NotificationType) -> Self {
+        Self {
             id: object.id.clone(),
+            space_id: object.space_id.clone(),
             name: object.name.clone(),
-            snippet: object
-                .snippet
-                .as_deref()
-                .unwrap_or("<no snippet>")
-                .to_string(),
-            due_date: object.due_date(),
-            creation_date: object.creation_date(),
+            snippet: object.snippet.clone(),
+            due_date: object.raw_due_date(),
+            creation_date: object.raw_creation_date(),
             proposed_by: object.proposed_by(),
             assignee: object.assignee(),
             notification_type,
-        })
+            type_key: object.type_field.as_ref().map(|t| t.key.clone()),
+            properties: object.properties.clone(),
+        }
+    }
+
+    /// Create notification content for the object that is only left in cache
+    pub fn from_cached(
+        id: &str,
+        cached_object: &CachedObject,
+        notification_type: NotificationType,
+    ) -> Self {
+        Self {
+            id: id.to_string(),
+            // Deleted objects can't be opened, so there is no need to link them
+            space_id: String::new(),
+            name: cached_object.name.clone(),
+            snippet: None,
+            creation_date: None,
+            due_date: cached_object.due_date.clone().flatten(),
+            proposed_by: cached_object.proposed_by.clone(),
+            assignee: cached_object.assignee.clone(),
+            notification_type,
+            type_key: cached_object.type_key.clone(),
+            // Properties are not cached, so such objects are routed only by type
+            properties: Vec::new(),
+        }
+    }
+}
+
+impl NotificationObject {
+    /// Link for opening the object in the Anytype app
+    pub fn deep_link(&self) -> Option<String> {
+        if self.space_id.is_empty() {
+            return None;
+        }
+
+        Some(format!(
+            "anytype://object?objectId={}&spaceId={}",
+            self.id, self.space_id
+        ))
+    }
+
+    /// Link for opening the object in the browser, built from the template with
+    /// {object_id} and {space_id} placeholders
+    pub fn web_link(&self, template: &str) -> Option<String> {
+        if self.space_id.is_empty() {
+            return None;
+        }
+
+        Some(
+            template
+                .replace("{object_id}", &self.id)
+                .replace("{space_id}", &self.space_id),
+        )
     }
 }
 
@@ -51,5 +128,8 @@ pub struct Notifications {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AnytypeToMatrixIdMap {
-    pub map: HashMap<String, String>, // anytype_id -> matrix_id
+    pub map: HashMap<String, String>,     // anytype_id -> matrix_id
+    pub names: HashMap<String, String>,   // anytype_id -> display name
+    pub timezones: HashMap<String, Tz>,   // anytype_id -> personal time zone
+    pub personal_digest: HashSet<String>, // anytype_id of members who get the personal digest
 }
@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+
+/// Body of the request to the space search endpoint
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchRequest {
+    pub query: String,
+    pub types: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<Sort>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filters: Option<FilterGroup>,
+}
+
+impl SearchRequest {
+    /// Search objects of the given types (all types if empty)
+    pub fn new(types: Vec<String>) -> Self {
+        Self {
+            query: String::new(),
+            types,
+            sort: None,
+            filters: None,
+        }
+    }
+
+    pub fn sort(mut self, sort: Sort) -> Self {
+        self.sort = Some(sort);
+        self
+    }
+
+    /// Add a condition, all conditions must match
+    pub fn filter(mut self, condition: FilterCondition) -> Self {
+        self.filters
+            .get_or_insert_with(|| FilterGroup {
+                operator: FilterOperator::And,
+                conditions: Vec::new(),
+            })
+            .conditions
+            .push(condition);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Sort {
+    pub property_key: String,
+    pub direction: SortDirection,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FilterGroup {
+    pub operator: FilterOperator,
+    pub conditions: Vec<FilterCondition>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterOperator {
+    And,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FilterCondition {
+    pub property_key: String,
+    pub condition: Condition,
+    #[serde(flatten)]
+    pub value: FilterValue,
+}
+
+impl FilterCondition {
+    /// Property value is equal to the given one
+    pub fn eq(property_key: &str, value: FilterValue) -> Self {
+        Self {
+            property_key: property_key.to_string(),
+            condition: Condition::Eq,
+            value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Condition {
+    Eq,
+}
+
+/// Value to compare the property with, serialized under the property format name
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterValue {
+    Checkbox(bool),
+}
@@ -0,0 +1,41 @@
+use reqwest::StatusCode;
+use thiserror::Error;
+
+/// Errors of requests to the Anytype API
+#[derive(Debug, Error)]
+pub enum AnytypeError {
+    #[error("ANYTYPE_URL must end with /objects, got: {0}")]
+    InvalidUrl(String),
+    #[error("ANYTYPE_TOKEN can not be used in the Authorization header")]
+    InvalidToken,
+    // The token is wrong or revoked, the request won't succeed until it is replaced
+    #[error("Anytype API rejected the token ({status}): {body}")]
+    Unauthorized { status: StatusCode, body: String },
+    #[error("bad status from Anytype API: {status}. Body: {body}")]
+    Status { status: StatusCode, body: String },
+    #[error("Anytype API request failed: {0}")]
+    Network(#[from] reqwest::Error),
+    #[error("decoding Anytype API response body: {source}. Raw response: {body}")]
+    Decode {
+        source: serde_json::Error,
+        body: String,
+    },
+}
+
+impl AnytypeError {
+    /// Create the error from the unsuccessful response status and body
+    pub fn from_status(status: StatusCode, body: String) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Self::Unauthorized { status, body },
+            _ => Self::Status { status, body },
+        }
+    }
+
+    /// Check if the requests won't succeed until ANYTYPE_URL or ANYTYPE_TOKEN is changed
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Self::InvalidUrl(_) | Self::InvalidToken | Self::Unauthorized { .. }
+        )
+    }
+}
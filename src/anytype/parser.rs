@@ -1,56 +1,241 @@
 use crate::{
     Token, Url,
-    anytype::entities::{api_response::ApiResponse, notification::AnytypeToMatrixIdMap},
+    anytype::{
+        entities::{
+            api_response::{
+                AnytypeObject, ApiResponse, MembersResponse, ObjectResponse, Pagination,
+            },
+            notification::AnytypeToMatrixIdMap,
+            search::SearchRequest,
+        },
+        error::AnytypeError,
+    },
 };
 
-use reqwest::{Client, header::HeaderMap};
-use std::{collections::HashMap, error::Error};
+use chrono_tz::Tz;
+use reqwest::{
+    Client, Response, StatusCode,
+    header::{HeaderMap, HeaderValue},
+};
+use serde::de::DeserializeOwned;
+use std::collections::{HashMap, HashSet};
 
-/// Get all Anytype objects from space
-pub async fn get_anytype_objects(
-    anytype_url: &Url,
-    anytype_token: &Token,
-) -> Result<ApiResponse, Box<dyn Error>> {
-    let client = Client::builder().build()?;
+/// Get the space endpoint (e.g. "search" or "members") from the space objects URL from ANYTYPE_URL
+fn space_endpoint_url(anytype_url: &Url, endpoint: &str) -> Result<Url, AnytypeError> {
+    let objects_url = anytype_url.0.trim().trim_end_matches('/');
 
+    match objects_url.strip_suffix("/objects") {
+        Some(space_url) => Ok(Url(format!("{space_url}/{endpoint}"))),
+        None => Err(AnytypeError::InvalidUrl(objects_url.to_string())),
+    }
+}
+
+/// Get the headers of the Anytype API request
+fn request_headers(anytype_token: &Token) -> Result<HeaderMap, AnytypeError> {
     let mut headers = HeaderMap::new();
-    headers.insert("Accept", "application/json".parse()?);
+    headers.insert("Accept", HeaderValue::from_static("application/json"));
     headers.insert(
         "Authorization",
-        format!("Bearer {}", anytype_token.0).parse()?,
+        format!("Bearer {}", anytype_token.0)
+            .parse()
+            .map_err(|_| AnytypeError::InvalidToken)?,
     ); // Anytype API token
 
+    Ok(headers)
+}
+
+/// Decode the body of the successful response, unsuccessful responses are returned as errors
+async fn decode_response<T: DeserializeOwned>(response: Response) -> Result<T, AnytypeError> {
+    // Check if the request was unsuccessful
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(AnytypeError::from_status(status, body));
+    }
+
+    let text = response.text().await?;
+    serde_json::from_str(&text).map_err(|source| AnytypeError::Decode { source, body: text })
+}
+
+/// Search one page of Anytype objects in space
+async fn search_anytype_objects_page(
+    client: &Client,
+    search_url: &Url,
+    anytype_token: &Token,
+    request: &SearchRequest,
+    offset: u64,
+    limit: u64,
+) -> Result<ApiResponse, AnytypeError> {
+    let headers = request_headers(anytype_token)?;
+
     let response = client
-        .get(anytype_url.0.clone())
+        .post(search_url.0.clone())
+        .query(&[("offset", offset), ("limit", limit)])
         .headers(headers)
+        .json(request)
         .send()
         .await?;
 
-    // Check if the request was unsuccessful
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().await.unwrap_or_default();
-        return Err(format!("Error: bad status from Anytype API: {status}. Body: {body}").into());
+    // Decode the response to ApiResponse structure
+    let body: ApiResponse = decode_response(response).await?;
+
+    Ok(body)
+}
+
+/// Request all pages of the paginated endpoint, `get_page` gets one page from the offset
+async fn get_all_pages<T, F, Fut>(mut get_page: F) -> Result<Vec<T>, AnytypeError>
+where
+    F: FnMut(u64) -> Fut,
+    Fut: Future<Output = Result<(Vec<T>, Option<Pagination>), AnytypeError>>,
+{
+    let mut items = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        let (data, pagination) = get_page(offset).await?;
+
+        let received = data.len() as u64;
+        items.extend(data);
+
+        // Stop if there are no more pages (or the API does not page its results)
+        match pagination {
+            Some(pagination) if pagination.has_more && received > 0 => {
+                offset = pagination.offset + received;
+            }
+            _ => break,
+        }
     }
 
-    // Decode the response to ApiResponse structure
-    let text = response.text().await?;
-    let body: ApiResponse = serde_json::from_str(&text)
-        .map_err(|e| format!("Error: decoding response body: {e}. Raw response: {text}"))?;
+    Ok(items)
+}
+
+/// Search Anytype objects in space with server-side filters, page by page
+pub async fn search_anytype_objects(
+    anytype_url: &Url,
+    anytype_token: &Token,
+    request: &SearchRequest,
+    page_size: u64,
+) -> Result<ApiResponse, AnytypeError> {
+    let client = &Client::builder().build()?;
+    let search_url = &space_endpoint_url(anytype_url, "search")?;
+
+    let data = get_all_pages(|offset| async move {
+        let page = search_anytype_objects_page(
+            client,
+            search_url,
+            anytype_token,
+            request,
+            offset,
+            page_size,
+        )
+        .await?;
+
+        Ok((page.data, page.pagination))
+    })
+    .await?;
+
+    Ok(ApiResponse {
+        data,
+        pagination: None,
+    })
+}
+
+/// Get Anytype object by id. Returns None if the object doesn't exist anymore
+pub async fn get_anytype_object(
+    anytype_url: &Url,
+    anytype_token: &Token,
+    object_id: &str,
+) -> Result<Option<AnytypeObject>, AnytypeError> {
+    let client = Client::builder().build()?;
+
+    let headers = request_headers(anytype_token)?;
+
+    let object_url = format!("{}/{object_id}", anytype_url.0.trim().trim_end_matches('/'));
+
+    let response = client.get(object_url).headers(headers).send().await?;
+
+    // Deleted objects are not found
+    if matches!(response.status(), StatusCode::NOT_FOUND | StatusCode::GONE) {
+        return Ok(None);
+    }
+
+    // Decode the response to ObjectResponse structure
+    let body: ObjectResponse = decode_response(response).await?;
+
+    Ok(Some(body.object))
+}
+
+/// Get one page of space members
+async fn get_space_members_page(
+    client: &Client,
+    members_url: &Url,
+    anytype_token: &Token,
+    offset: u64,
+    limit: u64,
+) -> Result<MembersResponse, AnytypeError> {
+    let headers = request_headers(anytype_token)?;
+
+    let response = client
+        .get(members_url.0.clone())
+        .query(&[("offset", offset), ("limit", limit)])
+        .headers(headers)
+        .send()
+        .await?;
+
+    // Decode the response to MembersResponse structure
+    let body: MembersResponse = decode_response(response).await?;
 
     Ok(body)
 }
 
+/// Get display names of all space members by their ids
+async fn get_member_names(
+    anytype_url: &Url,
+    anytype_token: &Token,
+    page_size: u64,
+) -> Result<HashMap<String, String>, AnytypeError> {
+    let client = &Client::builder().build()?;
+    let members_url = &space_endpoint_url(anytype_url, "members")?;
+
+    let members = get_all_pages(|offset| async move {
+        let page =
+            get_space_members_page(client, members_url, anytype_token, offset, page_size).await?;
+
+        Ok((page.data, page.pagination))
+    })
+    .await?;
+
+    let names = members
+        .into_iter()
+        .filter_map(|member| {
+            let name = member
+                .name
+                .filter(|n| !n.is_empty())
+                .or(member.global_name)?;
+            Some((member.id, name))
+        })
+        .collect();
+
+    Ok(names)
+}
+
 /// Get a mapping for finding the matrix id of the user by the anytype space member id
 pub async fn get_anytype_to_matrix_map(
     anytype_url: &Url,
     anytype_token: &Token,
     map_type: &str, // The name of the Anytype object type which contains the "anytype_id" and "matrix_id" properties
-) -> Result<AnytypeToMatrixIdMap, Box<dyn Error>> {
+    page_size: u64,
+) -> Result<AnytypeToMatrixIdMap, AnytypeError> {
     let mut map = HashMap::new();
-    let all_objects = get_anytype_objects(anytype_url, anytype_token).await?;
+    let mut timezones = HashMap::new();
+    let mut personal_digest = HashSet::new();
+
+    // Request only the mapping objects
+    let request = SearchRequest::new(vec![map_type.to_string()]);
+    let member_objects =
+        search_anytype_objects(anytype_url, anytype_token, &request, page_size).await?;
 
-    for o in &all_objects.data {
+    for o in &member_objects.data {
         // Skip objects that are not of the specified type
         if o.type_field.as_ref().map(|t| t.key.as_str()) != Some(map_type) {
             continue;
@@ -72,19 +257,64 @@ pub async fn get_anytype_to_matrix_map(
             .and_then(|p| p.text.as_ref())
             .cloned();
 
+        // Optional personal time zone of the member in the "timezone" text property
+        let timezone = o
+            .properties
+            .iter()
+            .find(|p| p.key == "timezone")
+            .and_then(|p| p.text.as_deref())
+            .filter(|tz| !tz.trim().is_empty());
+
+        if let (Some(anytype_id), Some(timezone)) = (&anytype_id, timezone) {
+            match timezone.trim().parse::<Tz>() {
+                Ok(tz) => {
+                    timezones.insert(anytype_id.clone(), tz);
+                }
+                Err(_) => eprintln!("Warn: unknown time zone \"{timezone}\" of {}", o.name),
+            }
+        }
+
+        // The member gets the personal digest if the "personal_digest" checkbox is set
+        let wants_digest = o
+            .properties
+            .iter()
+            .find(|p| p.key == "personal_digest")
+            .and_then(|p| p.checkbox)
+            .unwrap_or(false);
+
         // If both properties are found, add them to the map
         if let (Some(anytype_id), Some(matrix_id)) = (anytype_id, matrix_id) {
+            if wants_digest {
+                personal_digest.insert(anytype_id.clone());
+            }
+
             map.insert(anytype_id, matrix_id);
         }
     }
 
-    Ok(AnytypeToMatrixIdMap { map })
+    // Display names for members without Matrix ID. They are only a fallback, so the map is
+    // still usable without them
+    let names = get_member_names(anytype_url, anytype_token, page_size)
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("Warn: can not get space member names: {err}");
+            HashMap::new()
+        });
+
+    Ok(AnytypeToMatrixIdMap {
+        map,
+        names,
+        timezones,
+        personal_digest,
+    })
 }
 
 /// Find the matrix id of the user by the anytype space member id in the map
-pub fn find_matrix_user_id(map: &AnytypeToMatrixIdMap, anytype_id: &str) -> String {
-    map.map
-        .get(anytype_id)
-        .cloned()
-        .unwrap_or_else(|| "Unknown User".to_string())
+pub fn find_matrix_user_id(map: &AnytypeToMatrixIdMap, anytype_id: &str) -> Option<String> {
+    map.map.get(anytype_id).cloned()
+}
+
+/// Find the Anytype display name of the user by the anytype space member id in the map
+pub fn find_display_name(map: &AnytypeToMatrixIdMap, anytype_id: &str) -> Option<String> {
+    map.names.get(anytype_id).cloned()
 }
@@ -5,72 +5,52 @@ use crate::{
             api_response::{AnytypeObject, ApiResponse},
             cache::{AnytypeCache, CachedObject},
             notification::{NotificationObject, NotificationType, Notifications},
+            search::{FilterCondition, FilterValue, SearchRequest},
         },
-        parser::get_anytype_objects,
+        error::AnytypeError,
+        parser::{get_anytype_object, search_anytype_objects},
     },
     config::AppConfig,
+    error::AppError,
+    state::{CacheError, read_json, write_json},
 };
 
 use std::{
-    error::Error,
-    fs::{self, File},
+    collections::HashSet,
     path::Path,
     time::{Duration, SystemTime},
 };
 
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Utc};
 
-/// Cache Anytype objects in a file for find objects to notify in future checks
-async fn save_to_cache(path: &str, objects: &AnytypeCache) -> std::io::Result<()> {
-    let cache_path = Path::new(path);
-
-    // Create the directory if it doesn't exist
-    if let Some(parent) = cache_path.parent() {
-        fs::create_dir_all(parent)?;
-    }
+/// File with the state of the checked objects
+pub const CACHE_PATH: &str = "assets/cache.json";
 
-    let file = File::create(path)?;
-    serde_json::to_writer_pretty(file, objects)?;
-    Ok(())
+/// Cache Anytype objects in a file for find objects to notify in future checks
+async fn save_to_cache(path: &str, objects: &AnytypeCache) -> Result<(), CacheError> {
+    write_json(path, objects)
 }
 
 /// Load cached Anytype objects from a file
-async fn load_from_cache(path: &str) -> Result<AnytypeCache, Box<dyn Error>> {
-    let data = fs::read_to_string(path)?;
-    let cache: AnytypeCache = serde_json::from_str(&data)?;
-    Ok(cache)
+pub async fn load_from_cache(path: &str) -> Result<AnytypeCache, CacheError> {
+    read_json(path)
 }
 
 /// Create initial cache with actual objects at the first run
-async fn set_initial_cache(
-    current_objects: ApiResponse,
-    cache_path: &str,
-) -> Result<(), Box<dyn Error>> {
+async fn set_initial_cache(current_objects: ApiResponse, cache_path: &str, config: &AppConfig) {
     let mut initial_cache = AnytypeCache::default();
 
     for o in &current_objects.data {
-        let id = &o.id;
-        let notify_flag = o.is_notify_enabled();
-        let assignee = o.assignee();
-        let proposed_by = o.proposed_by();
+        // If notify is enabled, set cached object to already notified for the first run
         initial_cache.objects.insert(
-            id.clone(),
-            CachedObject {
-                notify: notify_flag,
-                // If notify is enabled, set cached object to already notified for the first run
-                notified: notify_flag,
-                assignee,
-                proposed_by,
-                notified_in_time: SystemTime::now(),
-            },
+            o.id.clone(),
+            CachedObject::new(o, o.is_notify_enabled(), config.status.as_ref()),
         );
     }
 
     if let Err(e) = save_to_cache(cache_path, &initial_cache).await {
         eprintln!("Failed to save initial cache: {e}");
     }
-
-    Ok(())
 }
 
 async fn process_cached_object(
@@ -96,6 +76,7 @@ async fn process_cached_object(
     }
 
     // Update the other fields
+    object.name = notification_object.name.clone();
     object.assignee = notification_object.assignee.clone();
     object.proposed_by = notification_object.proposed_by.clone();
 }
@@ -120,19 +101,14 @@ async fn process_renotify_object(
 }
 
 async fn process_new_object(
-    id: &str,
+    object: &AnytypeObject,
     notify_flag: bool,
     notification_object: &NotificationObject,
     cached_objects: &mut AnytypeCache,
     objects_to_notify: &mut Vec<NotificationObject>,
+    config: &AppConfig,
 ) {
-    let cached_object = CachedObject {
-        notify: notify_flag,
-        notified: notify_flag,
-        assignee: notification_object.assignee.clone(),
-        proposed_by: notification_object.proposed_by.clone(),
-        notified_in_time: SystemTime::now(),
-    };
+    let cached_object = CachedObject::new(object, notify_flag, config.status.as_ref());
 
     if notify_flag
         && !objects_to_notify // Check if object is already in the list
@@ -142,37 +118,135 @@ async fn process_new_object(
         objects_to_notify.push(notification_object.clone());
     }
 
-    cached_objects.objects.insert(id.to_string(), cached_object);
+    cached_objects
+        .objects
+        .insert(object.id.clone(), cached_object);
+}
+
+/// Search the objects with enabled notifications of the configured types
+pub async fn search_notify_objects(
+    anytype_url: &Url,
+    anytype_token: &Token,
+    config: &AppConfig,
+) -> Result<ApiResponse, AnytypeError> {
+    let request = SearchRequest::new(config.search.types.clone())
+        .sort(config.search.sort.clone())
+        .filter(FilterCondition::eq("notify", FilterValue::Checkbox(true)));
+
+    search_anytype_objects(anytype_url, anytype_token, &request, config.page_size).await
+}
+
+/// Updated cache, which is saved only after the notifications are sent.
+/// The changes behind the failed notifications are reverted, so they are notified again
+pub struct CacheTransaction {
+    previous: AnytypeCache,
+    updated: AnytypeCache,
+}
+
+impl CacheTransaction {
+    /// Revert the cached fields that the notification was detected by to the state before the
+    /// check. Other notifications about the object from this check are not found again
+    pub fn revert(&mut self, notification: &NotificationObject) {
+        let id = notification.id.as_str();
+        let previous = self.previous.objects.get(id);
+
+        // Removed objects are only dropped from the cache, so the whole entry is restored
+        if matches!(
+            notification.notification_type,
+            NotificationType::Archived | NotificationType::Deleted
+        ) {
+            if let Some(previous) = previous {
+                self.updated
+                    .objects
+                    .insert(id.to_string(), previous.clone());
+            }
+            return;
+        }
+
+        let Some(updated) = self.updated.objects.get_mut(id) else {
+            return;
+        };
+
+        match &notification.notification_type {
+            // The object is found as not notified again
+            NotificationType::New => updated.notified = false,
+            NotificationType::Unassigned | NotificationType::UpcomingDeadline => {
+                if let Some(previous) = previous {
+                    updated.notified_in_time = previous.notified_in_time;
+                }
+            }
+            NotificationType::AssigneeChanged { .. } => {
+                if let Some(previous) = previous {
+                    updated.assignee = previous.assignee.clone();
+                }
+            }
+            NotificationType::DeadlineChanged { .. } => {
+                updated.due_date = previous.and_then(|p| p.due_date.clone());
+            }
+            NotificationType::StatusChanged { .. } => {
+                updated.status = previous.and_then(|p| p.status.clone());
+            }
+            NotificationType::Overdue { .. } => {
+                updated.overdue_notified_in_time =
+                    previous.and_then(|p| p.overdue_notified_in_time);
+                updated.overdue_count = previous.map_or(0, |p| p.overdue_count);
+            }
+            NotificationType::Archived | NotificationType::Deleted => {}
+        }
+    }
+
+    /// Save the updated cache
+    pub async fn commit(self) -> Result<(), CacheError> {
+        save_to_cache(CACHE_PATH, &self.updated).await
+    }
 }
 
 /// Find Anytype objects with creation date after last check and
 /// objects that already existed, but need to notification again.
+/// The cache is not saved until the returned transaction is committed.
+/// None is returned at the first run, when the initial cache is created
 pub async fn find_objects_to_notify(
     anytype_url: &Url,
     anytype_token: &Token,
     config: &AppConfig,
-) -> Result<Option<Notifications>, Box<dyn Error>> {
-    let cache_path = "assets/cache.json";
+) -> Result<Option<(Notifications, CacheTransaction)>, AppError> {
+    let cache_path = CACHE_PATH;
 
-    let current_objects = get_anytype_objects(anytype_url, anytype_token).await?;
+    let mut current_objects = search_notify_objects(anytype_url, anytype_token, config).await?;
+
+    // Archived objects are only checked for closing notifications
+    let archived_objects: Vec<AnytypeObject> = current_objects
+        .data
+        .extract_if(.., |o| o.archived)
+        .collect();
 
     // At the first run create initial cache and exit
     if !Path::new(cache_path).exists() {
         println!("Cache not found. Saving current objects and exiting.");
-        set_initial_cache(current_objects, cache_path).await?;
+        set_initial_cache(current_objects, cache_path, config).await;
         return Ok(None);
     }
 
-    let mut cached_objects = load_from_cache(cache_path).await?;
+    let previous = load_from_cache(cache_path).await?;
+    let mut cached_objects = previous.clone();
 
     let mut objects_to_notify: Vec<NotificationObject> = Vec::new();
 
+    get_changed_objects(
+        &current_objects,
+        &mut cached_objects,
+        &mut objects_to_notify,
+        config,
+    )
+    .await;
+
     get_new_objects(
         &current_objects,
         &mut cached_objects,
         &mut objects_to_notify,
+        config,
     )
-    .await?;
+    .await;
 
     get_objects_for_renotify(
         &current_objects,
@@ -180,22 +254,30 @@ pub async fn find_objects_to_notify(
         &mut objects_to_notify,
         config,
     )
-    .await?;
+    .await;
 
-    // Save updated cache
-    if let Err(e) = save_to_cache(cache_path, &cached_objects).await {
-        eprintln!("Failed to save cache: {e}");
-    }
+    get_removed_objects(
+        anytype_url,
+        anytype_token,
+        &current_objects,
+        &archived_objects,
+        &mut cached_objects,
+        &mut objects_to_notify,
+        config,
+    )
+    .await;
 
-    let objects_to_notify = if objects_to_notify.is_empty() {
-        None
-    } else {
-        Some(Notifications {
-            objects: objects_to_notify,
-        })
+    let transaction = CacheTransaction {
+        previous,
+        updated: cached_objects,
     };
 
-    Ok(objects_to_notify)
+    Ok(Some((
+        Notifications {
+            objects: objects_to_notify,
+        },
+        transaction,
+    )))
 }
 
 /// Get new Anytype objects
@@ -203,14 +285,15 @@ async fn get_new_objects(
     current_objects: &ApiResponse,
     cached_objects: &mut AnytypeCache,
     objects_to_notify: &mut Vec<NotificationObject>,
-) -> Result<(), Box<dyn Error>> {
+    config: &AppConfig,
+) {
     // Compare current objects with cached and find unnotified objects with enabled notifications
     for o in &current_objects.data {
         let id = &o.id;
         let notify_flag = o.is_notify_enabled();
 
         // Create notification content
-        let notification_object = NotificationObject::new(o, NotificationType::New)?;
+        let notification_object = NotificationObject::new(o, NotificationType::New);
 
         match cached_objects.objects.get_mut(id) {
             Some(obj) => {
@@ -221,18 +304,208 @@ async fn get_new_objects(
             None => {
                 // Object doesn't exist in cache
                 process_new_object(
-                    id,
+                    o,
                     notify_flag,
                     &notification_object,
                     cached_objects,
                     objects_to_notify,
+                    config,
                 )
                 .await
             }
         }
     }
+}
+
+/// Get notified Anytype objects that were archived or deleted since the last check and
+/// remove objects that are not checked anymore from cache
+async fn get_removed_objects(
+    anytype_url: &Url,
+    anytype_token: &Token,
+    current_objects: &ApiResponse,
+    archived_objects: &[AnytypeObject],
+    cached_objects: &mut AnytypeCache,
+    objects_to_notify: &mut Vec<NotificationObject>,
+    config: &AppConfig,
+) {
+    let current_ids: HashSet<&str> = current_objects.data.iter().map(|o| o.id.as_str()).collect();
+
+    let missing_ids: Vec<String> = cached_objects
+        .objects
+        .keys()
+        .filter(|id| !current_ids.contains(id.as_str()))
+        .cloned()
+        .collect();
+
+    for id in missing_ids {
+        let Some(cached_object) = cached_objects.objects.get(&id) else {
+            continue;
+        };
+
+        // Objects with disabled notifications and objects of the types that are not searched
+        // anymore are just removed from cache
+        if !cached_object.notified || !is_searched_type(config, cached_object.type_key.as_deref()) {
+            cached_objects.objects.remove(&id);
+            continue;
+        }
+
+        let notification_object = match archived_objects.iter().find(|o| o.id == id) {
+            Some(o) => Some(NotificationObject::new(o, NotificationType::Archived)),
+            // Object is not found by search, check if it still exists
+            None => match get_anytype_object(anytype_url, anytype_token, &id).await {
+                Ok(None) => Some(NotificationObject::from_cached(
+                    &id,
+                    cached_object,
+                    NotificationType::Deleted,
+                )),
+                Ok(Some(o)) if o.archived => {
+                    Some(NotificationObject::new(&o, NotificationType::Archived))
+                }
+                // Notifications were disabled or the object is not checked anymore
+                Ok(Some(_)) => None,
+                // Keep the object in cache to check it again at the next run
+                Err(err) => {
+                    eprintln!("Warn: can not check removed object {id}: {err}");
+                    continue;
+                }
+            },
+        };
+
+        if let Some(notification_object) = notification_object {
+            objects_to_notify.push(notification_object);
+        }
+
+        cached_objects.objects.remove(&id);
+    }
+}
+
+/// Check if the objects of the type are searched. Objects of unknown type are checked anyway
+fn is_searched_type(config: &AppConfig, type_key: Option<&str>) -> bool {
+    let types = &config.search.types;
 
-    Ok(())
+    types.is_empty() || type_key.is_none_or(|key| types.iter().any(|t| t == key))
+}
+
+/// Get already notified Anytype objects whose properties were changed since the last check
+async fn get_changed_objects(
+    current_objects: &ApiResponse,
+    cached_objects: &mut AnytypeCache,
+    objects_to_notify: &mut Vec<NotificationObject>,
+    config: &AppConfig,
+) {
+    for o in &current_objects.data {
+        // if this object not need notify then skip
+        if !o.is_notify_enabled() {
+            continue;
+        }
+
+        if let Some(obj) = cached_objects.objects.get_mut(&o.id)
+            && obj.notified
+        {
+            check_assignee_changed(o, obj, objects_to_notify).await;
+            check_deadline_changed(o, obj, objects_to_notify).await;
+            check_status_changed(o, obj, objects_to_notify, config).await;
+        }
+    }
+}
+
+async fn check_assignee_changed(
+    object: &AnytypeObject,
+    cached_object: &mut CachedObject,
+    objects_to_notify: &mut Vec<NotificationObject>,
+) {
+    let assignee = object.assignee();
+
+    let added: Vec<String> = assignee
+        .iter()
+        .filter(|a| !cached_object.assignee.contains(a))
+        .cloned()
+        .collect();
+
+    let removed: Vec<String> = cached_object
+        .assignee
+        .iter()
+        .filter(|a| !assignee.contains(a))
+        .cloned()
+        .collect();
+
+    if !added.is_empty() || !removed.is_empty() {
+        let notification_object =
+            NotificationObject::new(object, NotificationType::AssigneeChanged { added, removed });
+        objects_to_notify.push(notification_object);
+    }
+
+    cached_object.assignee = assignee;
+}
+
+async fn check_deadline_changed(
+    object: &AnytypeObject,
+    cached_object: &mut CachedObject,
+    objects_to_notify: &mut Vec<NotificationObject>,
+) {
+    let due_date = object.raw_due_date();
+
+    // Compare only with the cached due date, the first time just remember it
+    if let Some(cached_due_date) = &cached_object.due_date
+        && !is_same_date(cached_due_date.as_deref(), due_date.as_deref())
+    {
+        let notification_object = NotificationObject::new(
+            object,
+            NotificationType::DeadlineChanged {
+                old: cached_due_date.clone(),
+                new: due_date.clone(),
+            },
+        );
+        objects_to_notify.push(notification_object);
+    }
+
+    cached_object.due_date = Some(due_date);
+}
+
+async fn check_status_changed(
+    object: &AnytypeObject,
+    cached_object: &mut CachedObject,
+    objects_to_notify: &mut Vec<NotificationObject>,
+    config: &AppConfig,
+) {
+    let Some(status_config) = &config.status else {
+        return;
+    };
+
+    let status = object.select(&status_config.property);
+
+    // Compare only with the cached status, the first time just remember it
+    if let Some(cached_status) = &cached_object.status
+        && *cached_status != status
+    {
+        let notification_object = NotificationObject::new(
+            object,
+            NotificationType::StatusChanged {
+                old: cached_status.clone(),
+                new: status.clone(),
+                done: object.is_done(Some(status_config)),
+            },
+        );
+        objects_to_notify.push(notification_object);
+    }
+
+    cached_object.status = Some(status);
+}
+
+/// Compare dates by the moment of time if possible, otherwise as strings
+fn is_same_date(a: Option<&str>, b: Option<&str>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            match (
+                DateTime::parse_from_rfc3339(a),
+                DateTime::parse_from_rfc3339(b),
+            ) {
+                (Ok(a), Ok(b)) => a == b,
+                _ => a == b,
+            }
+        }
+        (a, b) => a == b,
+    }
 }
 
 /// Get Anytype objects that already existed, but need to to notification again.
@@ -241,14 +514,14 @@ async fn get_objects_for_renotify(
     cached_objects: &mut AnytypeCache,
     objects_to_notify: &mut Vec<NotificationObject>,
     config: &AppConfig,
-) -> Result<(), Box<dyn Error>> {
+) {
     // Compare current objects with cached and find objects which need to renotify
     for o in &current_objects.data {
         let id = &o.id;
         let notify_flag = o.is_notify_enabled();
 
-        // if this object not need notify then skip
-        if !notify_flag {
+        // if this object not need notify or it is already done then skip
+        if !notify_flag || o.is_done(config.status.as_ref()) {
             continue;
         }
 
@@ -256,12 +529,11 @@ async fn get_objects_for_renotify(
         if let Some(obj) = cached_objects.objects.get_mut(id)
             && obj.notified
         {
-            check_unassigned(o, obj, objects_to_notify, config).await?;
-            check_deadline_upcoming(o, obj, objects_to_notify, config).await?;
+            check_unassigned(o, obj, objects_to_notify, config).await;
+            check_deadline_upcoming(o, obj, objects_to_notify, config).await;
+            check_overdue(o, obj, objects_to_notify, config).await;
         }
     }
-
-    Ok(())
 }
 
 async fn check_unassigned(
@@ -269,21 +541,21 @@ async fn check_unassigned(
     cached_object: &mut CachedObject,
     objects_to_notify: &mut Vec<NotificationObject>,
     config: &AppConfig,
-) -> Result<(), Box<dyn Error>> {
+) {
     let interval_days = config.renotify_interval.unassigned;
     let days_to_sec: u64 = 24 * 60 * 60;
     let time_now = SystemTime::now();
 
-    let notification_object = NotificationObject::new(object, NotificationType::Unassigned)?;
+    let notification_object = NotificationObject::new(object, NotificationType::Unassigned);
 
-    if time_now.duration_since(cached_object.notified_in_time)?
+    if time_now
+        .duration_since(cached_object.notified_in_time)
+        .unwrap_or_default()
         >= Duration::from_secs(interval_days * days_to_sec)
         && notification_object.assignee.is_empty()
     {
         process_renotify_object(cached_object, &notification_object, objects_to_notify).await
     }
-
-    Ok(())
 }
 
 async fn check_deadline_upcoming(
@@ -291,9 +563,9 @@ async fn check_deadline_upcoming(
     cached_object: &mut CachedObject,
     objects_to_notify: &mut Vec<NotificationObject>,
     config: &AppConfig,
-) -> Result<(), Box<dyn Error>> {
+) {
     let interval_days = config.renotify_interval.deadline_upcoming;
-    let time_now = Local::now();
+    let time_now = Utc::now().with_timezone(&config.timezone);
 
     if let Some(due_date_str) = object
         .properties
@@ -302,14 +574,60 @@ async fn check_deadline_upcoming(
         .and_then(|p| p.date.as_deref())
         && let Ok(due_date) = DateTime::parse_from_rfc3339(due_date_str)
     {
-        let time_diff = due_date.with_timezone(&Local) - time_now;
+        let due_date = due_date.with_timezone(&config.timezone);
+
+        // Days until the deadline are counted by calendar days in the configured time zone
+        let days_left = (due_date.date_naive() - time_now.date_naive()).num_days();
 
-        if time_diff.num_seconds() >= 0 && time_diff.num_days() as u64 <= interval_days {
+        // The deadline day itself is still upcoming, the task is overdue only from the next day
+        if days_left >= 0 && days_left as u64 <= interval_days {
             let notification_object =
-                NotificationObject::new(object, NotificationType::UpcomingDeadline)?;
+                NotificationObject::new(object, NotificationType::UpcomingDeadline);
             process_renotify_object(cached_object, &notification_object, objects_to_notify).await
         }
     }
+}
+
+async fn check_overdue(
+    object: &AnytypeObject,
+    cached_object: &mut CachedObject,
+    objects_to_notify: &mut Vec<NotificationObject>,
+    config: &AppConfig,
+) {
+    let interval_days = config.renotify_interval.overdue;
+    let days_to_sec: u64 = 24 * 60 * 60;
 
-    Ok(())
+    let time_now = Utc::now().with_timezone(&config.timezone);
+
+    // Archived objects and objects with not passed deadline are not overdue
+    if object.archived || !object.is_overdue(&time_now) {
+        cached_object.overdue_notified_in_time = None;
+        cached_object.overdue_count = 0;
+        return;
+    }
+
+    // Notify as soon as the deadline has passed and then every interval
+    let need_notify = match cached_object.overdue_notified_in_time {
+        Some(notified_in_time) => {
+            SystemTime::now()
+                .duration_since(notified_in_time)
+                .unwrap_or_default()
+                >= Duration::from_secs(interval_days * days_to_sec)
+        }
+        None => true,
+    };
+
+    if need_notify {
+        cached_object.overdue_count += 1;
+        cached_object.overdue_notified_in_time = Some(SystemTime::now());
+
+        let missed = cached_object.overdue_count;
+        let escalate = config
+            .overdue_escalate_after
+            .is_some_and(|after| missed > after);
+
+        let notification_object =
+            NotificationObject::new(object, NotificationType::Overdue { missed, escalate });
+        objects_to_notify.push(notification_object);
+    }
 }
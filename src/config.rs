@@ -1,13 +1,180 @@
-use crate::AnytypeToMatrixIdMapType;
+use crate::{
+    AnytypeToMatrixIdMapType,
+    anytype::entities::search::{Sort, SortDirection},
+    locale::{LocaleError, system_timezone},
+    matrix::client::RoomId,
+    templates::{TEMPLATE_KEYS, TemplateError},
+};
 
+use chrono::{NaiveTime, Weekday};
+use chrono_tz::Tz;
 use config::Config;
-use serde::Deserialize;
-use std::error::Error;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use thiserror::Error;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct RenotifyIntervalDays {
     pub unassigned: u64,
     pub deadline_upcoming: u64,
+    // Added later than the other intervals, so older configs don't have it
+    #[serde(default = "default_overdue_interval")]
+    pub overdue: u64,
+}
+
+fn default_overdue_interval() -> u64 {
+    1
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchConfig {
+    // Keys of the object types to check for notifications (all types if empty)
+    #[serde(default)]
+    pub types: Vec<String>,
+
+    // Order in which objects are requested from Anytype API
+    #[serde(default = "default_sort")]
+    pub sort: Sort,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            types: Vec::new(),
+            sort: default_sort(),
+        }
+    }
+}
+
+fn default_sort() -> Sort {
+    Sort {
+        property_key: "created_date".to_string(),
+        direction: SortDirection::Asc,
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StatusConfig {
+    // Key of the select property with the status of the object
+    pub property: String,
+
+    // Name of the status option which means the object is done
+    pub done: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Route {
+    // Key of the object type
+    pub type_key: String,
+
+    // Key of the select or multi-select property, which must have one of the values
+    #[serde(default)]
+    pub property: Option<String>,
+    #[serde(default)]
+    pub values: Vec<String>,
+
+    // Room for notifications about matched objects
+    pub room_id: RoomId,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Schedule {
+    // Time of sending in the time zone, "HH:MM"
+    #[serde(deserialize_with = "deserialize_time")]
+    pub time: NaiveTime,
+
+    // Day of the week for sending weekly, it is sent every day if not set
+    #[serde(default, deserialize_with = "deserialize_weekday")]
+    pub weekday: Option<Weekday>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DigestConfig {
+    #[serde(flatten)]
+    pub schedule: Schedule,
+
+    // Tasks with the deadline in this number of days are listed as due soon
+    #[serde(default = "default_due_soon_days")]
+    pub due_soon_days: u64,
+
+    // Send only the digest instead of a message for every task
+    #[serde(default)]
+    pub replace_notifications: bool,
+}
+
+fn default_due_soon_days() -> u64 {
+    3
+}
+
+fn deserialize_time<'de, D>(deserializer: D) -> Result<NaiveTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let time = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&time, "%H:%M")
+        .map_err(|_| serde::de::Error::custom(format!("invalid time \"{time}\", expected HH:MM")))
+}
+
+fn deserialize_weekday<'de, D>(deserializer: D) -> Result<Option<Weekday>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let weekday = String::deserialize(deserializer)?;
+    weekday
+        .parse()
+        .map(Some)
+        .map_err(|_| serde::de::Error::custom(format!("invalid weekday \"{weekday}\"")))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct QuietHoursConfig {
+    // Time when notifications stop and start again in the configured time zone, "HH:MM".
+    // The quiet time can go over midnight, e.g. from "22:00" to "08:00"
+    #[serde(default, deserialize_with = "deserialize_optional_time")]
+    pub start: Option<NaiveTime>,
+    #[serde(default, deserialize_with = "deserialize_optional_time")]
+    pub end: Option<NaiveTime>,
+
+    // Days when notifications are sent, every day if not set
+    #[serde(default = "all_weekdays", deserialize_with = "deserialize_weekdays")]
+    pub working_days: Vec<Weekday>,
+
+    // Notification types sent during the quiet time too, e.g. "overdue"
+    #[serde(default)]
+    pub bypass: Vec<String>,
+}
+
+fn all_weekdays() -> Vec<Weekday> {
+    vec![
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+        Weekday::Sat,
+        Weekday::Sun,
+    ]
+}
+
+fn deserialize_optional_time<'de, D>(deserializer: D) -> Result<Option<NaiveTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_time(deserializer).map(Some)
+}
+
+fn deserialize_weekdays<'de, D>(deserializer: D) -> Result<Vec<Weekday>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|weekday| {
+            weekday
+                .parse()
+                .map_err(|_| serde::de::Error::custom(format!("invalid weekday \"{weekday}\"")))
+        })
+        .collect()
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -16,19 +183,126 @@ pub struct AppConfig {
     pub anytype_to_matrix_id_map_type: AnytypeToMatrixIdMapType,
 
     // Interval of checking for new objects
-    #[allow(dead_code)]
-    pub interval_minutes: i64,
+    pub interval_minutes: u64,
 
     // Interval of checking for old objects for renotify
     pub renotify_interval: RenotifyIntervalDays,
+
+    // Number of overdue notifications after which the members who proposed the task are mentioned
+    #[serde(default)]
+    pub overdue_escalate_after: Option<u64>,
+
+    // Number of objects requested from Anytype API per page
+    #[serde(default = "default_page_size")]
+    pub page_size: u64,
+
+    // Which objects are requested from Anytype API for notifications
+    #[serde(default)]
+    pub search: SearchConfig,
+
+    // Status property for detecting status changes and completed objects
+    #[serde(default)]
+    pub status: Option<StatusConfig>,
+
+    // Rooms for notifications about different objects, MATRIX_ROOM_ID is used if none matches
+    #[serde(default)]
+    pub routes: Vec<Route>,
+
+    // Send a personal copy of notifications to assignees in direct messages
+    #[serde(default)]
+    pub direct_messages: bool,
+
+    // Template of the link for opening objects in the browser with {object_id} and {space_id}
+    #[serde(default)]
+    pub web_url_template: Option<String>,
+
+    // Language of the notifications: "ru" or "en" ("en-US" falls back to "en")
+    #[serde(default = "default_locale")]
+    pub locale: String,
+
+    // IANA time zone for dates in notifications and deadline calculations, system one by default
+    #[serde(default = "system_timezone")]
+    pub timezone: Tz,
+
+    // Scheduled summary of the open tasks
+    #[serde(default)]
+    pub digest: Option<DigestConfig>,
+
+    // Schedule of the personal digests sent to members in direct messages
+    #[serde(default)]
+    pub personal_digest: Option<Schedule>,
+
+    // Time when notifications are not sent, but queued until it ends
+    #[serde(default)]
+    pub quiet_hours: Option<QuietHoursConfig>,
+
+    // Paths to the template files by notification type, built-in templates are used for others
+    #[serde(default)]
+    pub templates: HashMap<String, String>,
+}
+
+fn default_locale() -> String {
+    "ru".to_string()
+}
+
+fn default_page_size() -> u64 {
+    100
+}
+
+/// Errors of loading the configuration from config.toml and .env
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("can not load config: {0}")]
+    Load(#[from] config::ConfigError),
+    #[error("{0} must be set in .env")]
+    MissingVar(&'static str),
+    #[error("invalid config: {0}")]
+    Invalid(String),
+    #[error("can not load locale: {0}")]
+    Locale(#[from] LocaleError),
+    #[error("can not load templates: {0}")]
+    Templates(#[from] TemplateError),
 }
 
 impl AppConfig {
-    pub fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+    pub fn from_file(path: &str) -> Result<Self, ConfigError> {
         let config = Config::builder()
             .add_source(config::File::with_name(path))
             .build()?;
 
-        Ok(config.try_deserialize()?)
+        let config: Self = config.try_deserialize()?;
+
+        if config.interval_minutes == 0 {
+            return Err(ConfigError::Invalid(
+                "interval_minutes must be greater than 0".to_string(),
+            ));
+        }
+
+        if config.page_size == 0 {
+            return Err(ConfigError::Invalid(
+                "page_size must be greater than 0".to_string(),
+            ));
+        }
+
+        if let Some(quiet_hours) = &config.quiet_hours {
+            if quiet_hours.start.is_some() != quiet_hours.end.is_some() {
+                return Err(ConfigError::Invalid(
+                    "quiet_hours.start and quiet_hours.end must be set together".to_string(),
+                ));
+            }
+
+            if let Some(key) = quiet_hours
+                .bypass
+                .iter()
+                .find(|k| !TEMPLATE_KEYS.contains(&k.as_str()))
+            {
+                return Err(ConfigError::Invalid(format!(
+                    "unknown notification type \"{key}\" in quiet_hours.bypass, expected one of: {}",
+                    TEMPLATE_KEYS.join(", ")
+                )));
+            }
+        }
+
+        Ok(config)
     }
 }
@@ -0,0 +1,402 @@
+use crate::{
+    anytype::entities::{
+        api_response::{AnytypeObject, ApiResponse},
+        cache::{AnytypeCache, CachedObject},
+        notification::{NotificationObject, NotificationType},
+    },
+    config::{AppConfig, DigestConfig, Route, Schedule},
+    matrix::client::RoomId,
+    routing::select_rooms,
+    state::{CacheError, read_json, write_json},
+};
+
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::Path, time::SystemTime};
+
+const DIGEST_STATE_PATH: &str = "assets/digest.json";
+
+/// When the digests were sent, kept between restarts
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DigestState {
+    pub last_sent: Option<SystemTime>,
+    // Rooms that already got the scheduled digest, while it failed in other rooms
+    #[serde(default)]
+    pub sent_rooms: Option<SentRooms>,
+    // Personal digests by Anytype space member id
+    #[serde(default)]
+    pub personal: HashMap<String, PersonalDigestState>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SentRooms {
+    // Scheduled time of the digest
+    pub scheduled: SystemTime,
+    pub rooms: Vec<RoomId>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PersonalDigestState {
+    pub last_sent: SystemTime,
+    // Ids of the tasks that were assigned to the member in the last digest
+    pub tasks: Vec<String>,
+}
+
+impl DigestState {
+    /// Load the state from assets/digest.json, the state is empty if there is no file
+    pub fn load() -> Result<Self, CacheError> {
+        if !Path::new(DIGEST_STATE_PATH).exists() {
+            return Ok(Self::default());
+        }
+
+        read_json(DIGEST_STATE_PATH)
+    }
+
+    /// Save the state to assets/digest.json
+    pub fn save(&self) -> Result<(), CacheError> {
+        write_json(DIGEST_STATE_PATH, self)
+    }
+}
+
+impl Schedule {
+    /// Get the last time the digest was scheduled at, not later than now
+    pub fn last_scheduled(&self, now: DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let timezone = now.timezone();
+        let mut date = now.date_naive();
+
+        // A weekly digest is scheduled at most a week ago
+        for _ in 0..=7 {
+            // Days when the time is skipped because of DST are skipped too
+            if self.weekday.is_none_or(|w| date.weekday() == w)
+                && let Some(scheduled) = timezone
+                    .from_local_datetime(&date.and_time(self.time))
+                    .earliest()
+                && scheduled <= now
+            {
+                return Some(scheduled);
+            }
+
+            date = date.pred_opt()?;
+        }
+
+        None
+    }
+}
+
+/// Get the notified objects that are not archived or done, with their cache entries.
+/// Objects that are not in the cache yet will be notified on the next check, so they are skipped
+fn open_tasks<'a>(
+    current_objects: &'a ApiResponse,
+    cached_objects: &'a AnytypeCache,
+    config: &'a AppConfig,
+) -> impl Iterator<Item = (&'a AnytypeObject, &'a CachedObject)> {
+    current_objects.data.iter().filter_map(|o| {
+        let cached_object = cached_objects.objects.get(&o.id)?;
+
+        if o.archived || !cached_object.notified || o.is_done(config.status.as_ref()) {
+            return None;
+        }
+
+        Some((o, cached_object))
+    })
+}
+
+fn overdue_type(cached_object: &CachedObject) -> NotificationType {
+    NotificationType::Overdue {
+        missed: cached_object.overdue_count,
+        escalate: false,
+    }
+}
+
+/// Open tasks grouped for the digest
+#[derive(Debug, Default)]
+pub struct Digest {
+    pub unassigned: Vec<NotificationObject>,
+    pub due_soon: Vec<NotificationObject>,
+    pub overdue: Vec<NotificationObject>,
+    // Created since the previous digest
+    pub new: Vec<NotificationObject>,
+}
+
+impl Digest {
+    /// Group the open tasks
+    pub fn build(
+        current_objects: &ApiResponse,
+        cached_objects: &AnytypeCache,
+        config: &AppConfig,
+        digest_config: &DigestConfig,
+        last_sent: SystemTime,
+    ) -> Self {
+        let mut digest = Self::default();
+
+        let now = Utc::now().with_timezone(&config.timezone);
+        let last_sent = DateTime::<Utc>::from(last_sent);
+
+        for (o, cached_object) in open_tasks(current_objects, cached_objects, config) {
+            if o.assignee().is_empty() {
+                digest
+                    .unassigned
+                    .push(NotificationObject::new(o, NotificationType::Unassigned));
+            }
+
+            if let Some(due_date) = o
+                .raw_due_date()
+                .and_then(|d| DateTime::parse_from_rfc3339(&d).ok())
+            {
+                let due_date = due_date.with_timezone(&config.timezone);
+
+                if o.is_overdue(&now) {
+                    digest
+                        .overdue
+                        .push(NotificationObject::new(o, overdue_type(cached_object)));
+                } else if (due_date.date_naive() - now.date_naive()).num_days() as u64
+                    <= digest_config.due_soon_days
+                {
+                    digest.due_soon.push(NotificationObject::new(
+                        o,
+                        NotificationType::UpcomingDeadline,
+                    ));
+                }
+            }
+
+            if o.raw_creation_date()
+                .and_then(|d| DateTime::parse_from_rfc3339(&d).ok())
+                .is_some_and(|created| created > last_sent)
+            {
+                digest
+                    .new
+                    .push(NotificationObject::new(o, NotificationType::New));
+            }
+        }
+
+        digest
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.unassigned.is_empty()
+            && self.due_soon.is_empty()
+            && self.overdue.is_empty()
+            && self.new.is_empty()
+    }
+
+    /// Groups with the catalog keys of their titles in the order of the message
+    pub fn groups(&self) -> [(&'static str, &[NotificationObject]); 4] {
+        [
+            ("digest_overdue", &self.overdue),
+            ("digest_due_soon", &self.due_soon),
+            ("digest_unassigned", &self.unassigned),
+            ("digest_new", &self.new),
+        ]
+    }
+
+    fn tasks(&self) -> impl Iterator<Item = &NotificationObject> {
+        self.unassigned
+            .iter()
+            .chain(&self.due_soon)
+            .chain(&self.overdue)
+            .chain(&self.new)
+    }
+
+    /// Get rooms of the routes of all listed tasks
+    pub fn rooms(&self, routes: &[Route], default_room: &RoomId) -> Vec<RoomId> {
+        let mut rooms: Vec<RoomId> = Vec::new();
+
+        for task in self.tasks() {
+            for room in select_rooms(routes, default_room, task) {
+                if !rooms.contains(&room) {
+                    rooms.push(room);
+                }
+            }
+        }
+
+        rooms
+    }
+
+    /// Get the part of the digest with the tasks routed to the room
+    pub fn for_room(&self, routes: &[Route], default_room: &RoomId, room: &RoomId) -> Self {
+        let filter = |tasks: &[NotificationObject]| -> Vec<NotificationObject> {
+            tasks
+                .iter()
+                .filter(|t| select_rooms(routes, default_room, t).contains(room))
+                .cloned()
+                .collect()
+        };
+
+        Self {
+            unassigned: filter(&self.unassigned),
+            due_soon: filter(&self.due_soon),
+            overdue: filter(&self.overdue),
+            new: filter(&self.new),
+        }
+    }
+}
+
+/// Tasks assigned to the member for the personal digest, sorted by the due date
+#[derive(Debug, Default)]
+pub struct PersonalDigest {
+    pub overdue: Vec<NotificationObject>,
+    // Assigned since the previous personal digest
+    pub newly_assigned: Vec<NotificationObject>,
+    pub assigned: Vec<NotificationObject>,
+}
+
+impl PersonalDigest {
+    /// Group the open tasks of the member, every task is listed once.
+    /// Without the previous digest no task is listed as newly assigned
+    pub fn build(
+        member_id: &str,
+        previous_tasks: Option<&[String]>,
+        current_objects: &ApiResponse,
+        cached_objects: &AnytypeCache,
+        config: &AppConfig,
+    ) -> Self {
+        let mut digest = Self::default();
+        let now = Utc::now().with_timezone(&config.timezone);
+
+        let mut tasks: Vec<(&AnytypeObject, &CachedObject, Option<DateTime<Utc>>)> =
+            open_tasks(current_objects, cached_objects, config)
+                .filter(|(o, _)| o.assignee().iter().any(|a| a == member_id))
+                .map(|(o, cached_object)| {
+                    let due_date = o
+                        .raw_due_date()
+                        .and_then(|d| DateTime::parse_from_rfc3339(&d).ok())
+                        .map(|d| d.with_timezone(&Utc));
+                    (o, cached_object, due_date)
+                })
+                .collect();
+
+        // Tasks without the deadline are the last
+        tasks.sort_by_key(|(_, _, due_date)| (due_date.is_none(), *due_date));
+
+        for (o, cached_object, _) in tasks {
+            if o.is_overdue(&now) {
+                digest
+                    .overdue
+                    .push(NotificationObject::new(o, overdue_type(cached_object)));
+            } else if previous_tasks.is_some_and(|tasks| !tasks.contains(&o.id)) {
+                digest
+                    .newly_assigned
+                    .push(NotificationObject::new(o, NotificationType::New));
+            } else {
+                digest.assigned.push(NotificationObject::new(
+                    o,
+                    NotificationType::UpcomingDeadline,
+                ));
+            }
+        }
+
+        digest
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.overdue.is_empty() && self.newly_assigned.is_empty() && self.assigned.is_empty()
+    }
+
+    /// Ids of all listed tasks
+    pub fn task_ids(&self) -> Vec<String> {
+        self.overdue
+            .iter()
+            .chain(&self.newly_assigned)
+            .chain(&self.assigned)
+            .map(|t| t.id.clone())
+            .collect()
+    }
+
+    /// Groups with the catalog keys of their titles in the order of the message
+    pub fn groups(&self) -> [(&'static str, &[NotificationObject]); 3] {
+        [
+            ("digest_overdue", &self.overdue),
+            ("digest_newly_assigned", &self.newly_assigned),
+            ("digest_assigned", &self.assigned),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveTime, Weekday};
+    use chrono_tz::Europe::Berlin;
+
+    fn schedule(hour: u32, minute: u32, weekday: Option<Weekday>) -> Schedule {
+        Schedule {
+            time: NaiveTime::from_hms_opt(hour, minute, 0).unwrap(),
+            weekday,
+        }
+    }
+
+    fn berlin(month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Tz> {
+        Berlin
+            .with_ymd_and_hms(2026, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn daily_schedule() {
+        let schedule = schedule(9, 0, None);
+
+        assert_eq!(
+            schedule.last_scheduled(berlin(1, 7, 12, 0)),
+            Some(berlin(1, 7, 9, 0))
+        );
+        assert_eq!(
+            schedule.last_scheduled(berlin(1, 7, 9, 0)),
+            Some(berlin(1, 7, 9, 0))
+        );
+        assert_eq!(
+            schedule.last_scheduled(berlin(1, 7, 8, 59)),
+            Some(berlin(1, 6, 9, 0))
+        );
+    }
+
+    #[test]
+    fn weekly_schedule() {
+        // January 5, 2026 is Monday
+        let schedule = schedule(9, 0, Some(Weekday::Mon));
+
+        assert_eq!(
+            schedule.last_scheduled(berlin(1, 7, 12, 0)),
+            Some(berlin(1, 5, 9, 0))
+        );
+        assert_eq!(
+            schedule.last_scheduled(berlin(1, 5, 9, 0)),
+            Some(berlin(1, 5, 9, 0))
+        );
+        assert_eq!(
+            schedule.last_scheduled(berlin(1, 5, 8, 59)),
+            Some(Berlin.with_ymd_and_hms(2025, 12, 29, 9, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn skips_time_missing_because_of_dst() {
+        // On March 29, 2026 the clocks go from 02:00 to 03:00 in Berlin
+        let schedule = schedule(2, 30, None);
+
+        assert_eq!(
+            schedule.last_scheduled(berlin(3, 29, 12, 0)),
+            Some(berlin(3, 28, 2, 30))
+        );
+        assert_eq!(
+            schedule.last_scheduled(berlin(3, 30, 3, 0)),
+            Some(berlin(3, 30, 2, 30))
+        );
+    }
+
+    #[test]
+    fn uses_earliest_of_repeated_time() {
+        // On October 25, 2026 the clocks go from 03:00 back to 02:00 in Berlin
+        let schedule = schedule(2, 30, None);
+        let now = Utc
+            .with_ymd_and_hms(2026, 10, 25, 1, 0, 0)
+            .unwrap()
+            .with_timezone(&Berlin);
+
+        let scheduled = schedule.last_scheduled(now).unwrap();
+        assert_eq!(
+            scheduled.with_timezone(&Utc),
+            Utc.with_ymd_and_hms(2026, 10, 25, 0, 30, 0).unwrap()
+        );
+    }
+}
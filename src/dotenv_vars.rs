@@ -1,6 +1,4 @@
-use std::error::Error;
-
-use crate::{Token, Url, matrix::client::RoomId};
+use crate::{Token, Url, config::ConfigError, matrix::client::RoomId};
 
 #[derive(Debug, Clone)]
 pub struct AnytypeVars {
@@ -14,16 +12,28 @@ pub struct MatrixVars {
     pub room_id: RoomId,
 }
 
-pub fn get_anytype_env_vars() -> Result<AnytypeVars, Box<dyn Error>> {
-    let url = Url(std::env::var("ANYTYPE_URL")?); // Anytype space URL
-    let token = Token(std::env::var("ANYTYPE_TOKEN")?); // Anytype API token
+/// Get the required variable from the environment
+pub fn env_var(name: &'static str) -> Result<String, ConfigError> {
+    std::env::var(name).map_err(|_| ConfigError::MissingVar(name))
+}
+
+/// Get the optional variable from the environment, an empty value is the same as unset
+pub fn optional_env_var(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+}
+
+pub fn get_anytype_env_vars() -> Result<AnytypeVars, ConfigError> {
+    let url = Url(env_var("ANYTYPE_URL")?); // Anytype space URL
+    let token = Token(env_var("ANYTYPE_TOKEN")?); // Anytype API token
 
     Ok(AnytypeVars { url, token })
 }
 
-pub fn get_matrix_env_vars() -> Result<MatrixVars, Box<dyn Error>> {
-    let server = Url(std::env::var("MATRIX_SERVER")?);
-    let room_id = RoomId(std::env::var("MATRIX_ROOM_ID")?);
+pub fn get_matrix_env_vars() -> Result<MatrixVars, ConfigError> {
+    let server = Url(env_var("MATRIX_SERVER")?);
+    let room_id = RoomId(env_var("MATRIX_ROOM_ID")?);
 
     Ok(MatrixVars { server, room_id })
 }
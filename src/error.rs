@@ -0,0 +1,98 @@
+use crate::{
+    anytype::error::AnytypeError,
+    config::ConfigError,
+    matrix::error::{AuthError, SendError, SessionError},
+    state::CacheError,
+};
+
+use std::fmt::Display;
+use thiserror::Error;
+
+/// Errors of the service by the failed subsystem
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    Anytype(#[from] AnytypeError),
+    #[error(transparent)]
+    Cache(#[from] CacheError),
+    #[error(transparent)]
+    MatrixAuth(#[from] AuthError),
+    #[error(transparent)]
+    MatrixSend(#[from] SendError),
+    // The error with the description of the failed step
+    #[error("{context}: {source}")]
+    Context {
+        context: String,
+        source: Box<AppError>,
+    },
+    // Errors of the independent steps, e.g. notifications and digests of one check
+    #[error("{}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    Multiple(Vec<AppError>),
+}
+
+impl AppError {
+    /// Exit code of the process by the error category
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Config(_)
+            | Self::MatrixAuth(
+                AuthError::Config(_) | AuthError::Session(SessionError::MissingKey),
+            ) => 2,
+            Self::Anytype(_) => 3,
+            Self::Cache(_) => 4,
+            Self::MatrixAuth(_) => 5,
+            Self::MatrixSend(err) if err.is_auth_failure() => 5,
+            Self::MatrixSend(_) => 6,
+            Self::Context { source, .. } => source.exit_code(),
+            Self::Multiple(errors) => errors.first().map_or(1, AppError::exit_code),
+        }
+    }
+
+    /// Check if the error won't go away on the next check, so the service must be stopped
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            Self::Config(_) | Self::MatrixAuth(_) => true,
+            Self::Anytype(err) => err.is_fatal(),
+            // Tokens that can't be refreshed are replaced by logging in again after the restart
+            Self::MatrixSend(err) => err.is_auth_failure(),
+            Self::Cache(_) => false,
+            Self::Context { source, .. } => source.is_fatal(),
+            Self::Multiple(errors) => errors.iter().any(AppError::is_fatal),
+        }
+    }
+
+    /// Check if the request was rejected and retrying it gives the same error,
+    /// e.g. the bot is not in the room
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            Self::MatrixSend(err) => err.is_permanent(),
+            Self::Context { source, .. } => source.is_permanent(),
+            Self::Multiple(errors) => {
+                !errors.is_empty() && errors.iter().all(AppError::is_permanent)
+            }
+            Self::Config(_) | Self::Anytype(_) | Self::Cache(_) | Self::MatrixAuth(_) => false,
+        }
+    }
+}
+
+/// Add the description of the failed step to the error
+pub trait Context<T> {
+    fn context<C: Display>(self, context: C) -> Result<T, AppError>;
+
+    fn with_context<C: Display, F: FnOnce() -> C>(self, context: F) -> Result<T, AppError>;
+}
+
+impl<T, E: Into<AppError>> Context<T> for Result<T, E> {
+    fn context<C: Display>(self, context: C) -> Result<T, AppError> {
+        self.with_context(|| context)
+    }
+
+    fn with_context<C: Display, F: FnOnce() -> C>(self, context: F) -> Result<T, AppError> {
+        self.map_err(|err| AppError::Context {
+            context: context().to_string(),
+            source: Box::new(err.into()),
+        })
+    }
+}
@@ -0,0 +1,164 @@
+use chrono::{DateTime, Datelike};
+use chrono_tz::Tz;
+use config::{Config, FileFormat};
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Locales with built-in catalogs and templates
+pub const LOCALES: [&str; 2] = ["ru", "en"];
+
+/// Locale which is used when a string is missing in the other catalogs
+pub const FALLBACK_LOCALE: &str = "en";
+
+const MONTH_KEYS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+/// Built-in catalogs of strings
+fn builtin_catalog(locale: &str) -> Option<&'static str> {
+    match locale {
+        "ru" => Some(include_str!("../locales/ru.toml")),
+        "en" => Some(include_str!("../locales/en.toml")),
+        _ => None,
+    }
+}
+
+/// Errors of loading the built-in catalogs
+#[derive(Debug, Error)]
+pub enum LocaleError {
+    #[error("invalid catalog \"{name}\": {source}")]
+    Catalog {
+        name: &'static str,
+        source: Box<config::ConfigError>,
+    },
+}
+
+/// Strings and date formats of the notifications language
+#[derive(Debug, Clone)]
+pub struct Locale {
+    // Names of the used catalogs in the lookup order
+    chain: Vec<&'static str>,
+    catalogs: Vec<HashMap<String, String>>,
+    // Time zone in which dates are shown
+    timezone: Tz,
+}
+
+impl Locale {
+    /// Build the fallback chain for the locale: "en-US" -> "en" -> fallback locale
+    pub fn new(locale: &str, timezone: Tz) -> Result<Self, LocaleError> {
+        let language = locale.split(['-', '_']).next().unwrap_or(locale);
+
+        let mut chain: Vec<&'static str> = Vec::new();
+        for name in [locale, language, FALLBACK_LOCALE] {
+            let builtin = LOCALES.into_iter().find(|l| l.eq_ignore_ascii_case(name));
+
+            if let Some(builtin) = builtin
+                && !chain.contains(&builtin)
+            {
+                chain.push(builtin);
+            }
+        }
+
+        if chain.first() == Some(&FALLBACK_LOCALE)
+            && !language.eq_ignore_ascii_case(FALLBACK_LOCALE)
+        {
+            eprintln!("Warn: locale \"{locale}\" is not supported, using \"{FALLBACK_LOCALE}\"");
+        }
+
+        let mut catalogs = Vec::new();
+        for name in &chain {
+            let text = builtin_catalog(name).unwrap_or_default();
+            let catalog: HashMap<String, String> = Config::builder()
+                .add_source(config::File::from_str(text, FileFormat::Toml))
+                .build()
+                .and_then(Config::try_deserialize)
+                .map_err(|source| LocaleError::Catalog {
+                    name,
+                    source: Box::new(source),
+                })?;
+
+            catalogs.push(catalog);
+        }
+
+        Ok(Self {
+            chain,
+            catalogs,
+            timezone,
+        })
+    }
+
+    /// Same locale with dates shown in another time zone
+    pub fn with_timezone(&self, timezone: Tz) -> Self {
+        Self {
+            timezone,
+            ..self.clone()
+        }
+    }
+
+    /// Names of the used locales in the lookup order
+    pub fn chain(&self) -> &[&'static str] {
+        &self.chain
+    }
+
+    /// Get the string from the first catalog that has it, the key itself is returned otherwise
+    pub fn text(&self, key: &str) -> String {
+        self.catalogs
+            .iter()
+            .find_map(|c| c.get(key))
+            .cloned()
+            .unwrap_or_else(|| key.to_string())
+    }
+
+    /// Format the RFC 3339 date as a date without time
+    pub fn format_date(&self, raw: &str) -> String {
+        self.format(raw, "date_format")
+    }
+
+    /// Format the RFC 3339 date as a date with time
+    pub fn format_datetime(&self, raw: &str) -> String {
+        self.format(raw, "datetime_format")
+    }
+
+    fn format(&self, raw: &str, layout_key: &str) -> String {
+        // Converting the date from UTC to the time zone
+        let local_time = match DateTime::parse_from_rfc3339(raw) {
+            Ok(dt) => dt.with_timezone(&self.timezone),
+            Err(_) => return self.text("invalid_date").replace("{date}", raw),
+        };
+
+        let month = MONTH_KEYS[local_time.month0() as usize];
+
+        self.text(layout_key)
+            .replace("{day}", &local_time.day().to_string())
+            .replace("{month}", &self.text(month))
+            .replace("{year}", &local_time.year().to_string())
+            .replace(
+                "{time}",
+                &local_time.format(&self.text("time_format")).to_string(),
+            )
+    }
+}
+
+/// Time zone of the system (TZ variable or /etc/localtime), UTC if it is unknown
+pub fn system_timezone() -> Tz {
+    std::env::var("TZ")
+        .ok()
+        .and_then(|name| name.trim_start_matches(':').parse().ok())
+        .or_else(|| {
+            iana_time_zone::get_timezone()
+                .ok()
+                .and_then(|name| name.parse().ok())
+        })
+        .unwrap_or(Tz::UTC)
+}
@@ -1,17 +1,17 @@
 mod anytype;
 mod config;
+mod digest;
 mod dotenv_vars;
+mod error;
+mod locale;
 mod matrix;
+mod queue;
+mod routing;
+mod service;
+mod state;
+mod templates;
 
-use crate::{
-    anytype::{
-        entities::notification::{NotificationType},
-        parser::get_anytype_to_matrix_map,
-        sentinel::find_objects_to_notify,
-    },
-    config::AppConfig,
-    matrix::{client::set_client, messages},
-};
+use crate::{config::AppConfig, error::AppError, service::Service};
 
 use dotenv::dotenv;
 use serde::{Deserialize, Serialize};
@@ -30,111 +30,29 @@ pub struct AnytypeToMatrixIdMapType(pub String);
 async fn main() {
     dotenv().ok(); // Load .env
 
-    let anytype_env = dotenv_vars::get_anytype_env_vars().unwrap_or_else(|err| {
-        println!("Error: ANYTYPE_TOKEN and ANYTYPE_TOKEN must be set in .env\nDetails: {err}");
-        process::exit(1);
-    });
-
-    // Load config from config.toml
-    let config = AppConfig::from_file("config.toml").unwrap_or_else(|err| {
-        println!("Error: {err}");
-        process::exit(1);
-    });
-
-    // Anytype object type which contains the "anytype_id" and "matrix_id" properties
-    let id_map_type = &config.anytype_to_matrix_id_map_type;
+    // The exit code shows the category of the error
+    if let Err(err) = run().await {
+        eprintln!("Error: {err}");
+        process::exit(err.exit_code());
+    }
+}
 
-    let objects_to_notify =
-        match find_objects_to_notify(&anytype_env.url, &anytype_env.token, &config).await {
-            Ok(data) => data,
-            Err(err) => {
-                eprintln!("Error: find_new_objects failed: {err:#}");
-                process::exit(1);
-            }
-        };
+async fn run() -> Result<(), AppError> {
+    // Run a single check and exit (for running from cron)
+    let once = std::env::args().skip(1).any(|arg| arg == "--once");
 
-    // Get mapping for finding matrix user ids by anytype space member ids
-    let matrix_id_map =
-        match get_anytype_to_matrix_map(&anytype_env.url, &anytype_env.token, &id_map_type.0).await
-        {
-            Ok(data) => data,
-            Err(err) => {
-                eprintln!("Error: can not get anytype to matrix id mapping: {err:#}");
-                process::exit(1);
-            }
-        };
+    let anytype_env = dotenv_vars::get_anytype_env_vars()?;
 
-    let matrix_env = dotenv_vars::get_matrix_env_vars().unwrap_or_else(|err| {
-        println!("Error: MATRIX_SERVER and MATRIX_ROOM_ID must be set in .env\nDetails: {err}");
-        process::exit(1);
-    });
+    // Load config from config.toml
+    let config = AppConfig::from_file("config.toml")?;
 
-    let matrix_client = match set_client(matrix_env.server).await {
-        Ok(cl) => cl,
-        Err(err) => {
-            eprintln!("Error: {err}");
-            process::exit(1);
-        }
-    };
+    let matrix_env = dotenv_vars::get_matrix_env_vars()?;
 
-    let device_id = match matrix_client.auth().who_am_i().await {
-        Ok(me) => me,
-        Err(err) => {
-            eprintln!("Error: {err}");
-            process::exit(1);
-        }
-    }
-    .device_id;
+    let mut service = Service::new(config, anytype_env, matrix_env).await?;
 
-    if objects_to_notify.is_none() {
-        println!("No objects to notify");
-        return;
+    if once {
+        return service.check().await;
     }
 
-    // Check if there are objects to notify
-    let objects_to_notify = objects_to_notify.unwrap();
-    println!(
-        "Found {} objects to notify",
-        objects_to_notify.objects.len()
-    );
-
-    // Create and send notifications for all objects
-    for object in objects_to_notify.objects {
-        match object.notification_type {
-            NotificationType::New => {
-                match messages::send_message(
-                    object,
-                    &matrix_id_map,
-                    &matrix_client,
-                    &matrix_env.room_id,
-                    &device_id,
-                )
-                .await
-                {
-                    Ok(_) => {}
-                    Err(err) => {
-                        eprintln!("Error sending new notification: {err}");
-                        process::exit(1);
-                    }
-                }
-            }
-            NotificationType::Unassigned | NotificationType::UpcomingDeadline => {
-                match messages::send_renotify_message(
-                    object,
-                    &matrix_id_map,
-                    &matrix_client,
-                    &matrix_env.room_id,
-                    &device_id,
-                )
-                .await
-                {
-                    Ok(_) => {}
-                    Err(err) => {
-                        eprintln!("Error sending renotify notification: {err}");
-                        process::exit(1);
-                    }
-                }
-            }
-        }
-    }
+    service.run().await
 }
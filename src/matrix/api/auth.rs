@@ -1,11 +1,12 @@
 use crate::{
     Token,
-    matrix::client::{Client, Password, User},
+    matrix::{
+        client::{Client, Password, User},
+        error::{AuthError, RequestError},
+    },
 };
 
-use reqwest::header::HeaderMap;
 use serde::{Deserialize, Serialize};
-use std::error::Error;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct DeviceId(pub String);
@@ -18,8 +19,9 @@ pub struct LoginResponse {
     pub access_token: Token,
     pub device_id: DeviceId,
     pub user_id: UserId,
-    pub refresh_token: Token,
-    pub expires_in_ms: i64,
+    // Сервер может не выдавать refresh токены, тогда access токен не истекает
+    pub refresh_token: Option<Token>,
+    pub expires_in_ms: Option<i64>,
 }
 
 #[derive(Serialize)]
@@ -41,19 +43,18 @@ pub struct Identifier {
     user: String,
 }
 
-#[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 pub struct WhoAmI {
     pub device_id: DeviceId,
     pub user_id: UserId,
 }
 
-#[allow(dead_code)]
 #[derive(Deserialize)]
 pub struct NewTokens {
     access_token: Token,
-    expires_in_ms: i64,
-    refresh_token: Token,
+    expires_in_ms: Option<i64>,
+    // Если сервер не выдал новый refresh токен, то продолжает действовать старый
+    refresh_token: Option<Token>,
 }
 
 #[derive(Serialize)]
@@ -74,10 +75,7 @@ impl Auth {
     /// и паролю пользователя
     ///
     /// Добавляет в ```Client``` полученные токены и возвращает ```Client```
-    pub async fn login(mut self, user: User, password: Password) -> Result<Client, Box<dyn Error>> {
-        let mut headers = HeaderMap::new();
-        headers.insert("Accept", "application/json".parse()?);
-
+    pub async fn login(self, user: User, password: Password) -> Result<Client, AuthError> {
         let identifier_val = Identifier {
             type_: "m.id.user".to_string(),
             user: user.0,
@@ -93,60 +91,73 @@ impl Auth {
 
         let response = self
             .client
-            .post("/_matrix/client/v3/login", headers, body)
-            .await?;
-
-        let result = response.json::<LoginResponse>().await?;
-
-        self.client
-            .set_tokens(result.access_token.clone(), result.refresh_token.clone());
+            .public_post("/_matrix/client/v3/login", body)
+            .await
+            .map_err(AuthError::Login)?;
+
+        let result = response
+            .json::<LoginResponse>()
+            .await
+            .map_err(|err| AuthError::Login(RequestError::Decode(err)))?;
+
+        self.client.set_tokens(
+            result.access_token,
+            result.refresh_token,
+            result.expires_in_ms,
+        );
+        self.client.set_identity(result.user_id, result.device_id);
 
         Ok(self.client)
     }
 
     /// Эта функция делает **get** запрос к **/_matrix/client/v3/account/whoami** для получения данных о
-    /// данном сеансе. Если токен истек вернет ошибку
-    pub async fn who_am_i(&self) -> Result<WhoAmI, Box<dyn Error>> {
-        let mut headers = HeaderMap::new();
-        headers.insert("Accept", "application/json".parse()?);
-
-        headers.insert(
-            "Authorization",
-            format!("Bearer {}", self.client.get_access_token().0).parse()?,
-        );
-
+    /// данном сеансе. Истекший токен обновляется, а если это невозможно, вернет ошибку
+    pub async fn who_am_i(&self) -> Result<WhoAmI, AuthError> {
         let response = self
             .client
-            .get("/_matrix/client/v3/account/whoami", headers)
-            .await?;
+            .get("/_matrix/client/v3/account/whoami")
+            .await
+            .map_err(AuthError::WhoAmI)?;
 
-        let result = response.json::<WhoAmI>().await?;
+        let result = response
+            .json::<WhoAmI>()
+            .await
+            .map_err(|err| AuthError::WhoAmI(RequestError::Decode(err)))?;
 
         Ok(result)
     }
 
     /// Эта функция делает **post** запрос к **/_matrix/client/v3/refresh** для обновления токенов.
+    /// Если у ```Client``` нет refresh токена, вернет ошибку
     ///
     /// Добавляет в ```Client``` полученные токены и возвращает ```Client```
-    pub async fn refresh(mut self) -> Result<Client, Box<dyn Error>> {
-        let mut headers = HeaderMap::new();
-        headers.insert("Accept", "application/json".parse()?);
+    pub async fn refresh(self) -> Result<Client, AuthError> {
+        let refresh_token = self
+            .client
+            .get_refresh_token()
+            .ok_or(AuthError::NoRefreshToken)?;
 
         let response = self
             .client
-            .post(
+            .public_post(
                 "/_matrix/client/v3/refresh",
-                headers,
                 RefreshRequest {
-                    refresh_token: self.client.get_refresh_token(),
+                    refresh_token: refresh_token.clone(),
                 },
             )
-            .await?;
-
-        let result = response.json::<NewTokens>().await?;
-
-        self.client
-            .set_tokens(result.access_token.clone(), result.refresh_token.clone());
+            .await
+            .map_err(AuthError::Refresh)?;
+
+        let result = response
+            .json::<NewTokens>()
+            .await
+            .map_err(|err| AuthError::Refresh(RequestError::Decode(err)))?;
+
+        self.client.set_tokens(
+            result.access_token,
+            result.refresh_token.or(Some(refresh_token)),
+            result.expires_in_ms,
+        );
 
         Ok(self.client)
     }
@@ -1,12 +1,15 @@
-use std::error::Error;
+use std::collections::HashMap;
 
-use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::matrix::{
-    api::auth::DeviceId,
+    api::auth::{DeviceId, UserId},
     client::{Client, RoomId},
+    direct_rooms::DirectRooms,
+    error::{RequestError, SendError},
+    message_builder::FormattedMessage,
 };
 
 #[derive(Serialize)]
@@ -15,6 +18,14 @@ pub struct MessageBody {
     pub msgtype: String,
     pub format: String,
     pub formatted_body: String,
+    #[serde(rename = "m.mentions")]
+    pub mentions: Mentions,
+}
+
+/// Пользователи, которых нужно упомянуть (подсветить и уведомить) в сообщении
+#[derive(Serialize)]
+pub struct Mentions {
+    pub user_ids: Vec<String>,
 }
 
 #[allow(dead_code)]
@@ -24,6 +35,21 @@ pub struct EventId {
     pub value: String,
 }
 
+#[derive(Serialize)]
+pub struct CreateRoomRequest {
+    pub is_direct: bool,
+    pub invite: Vec<String>,
+    pub preset: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateRoomResponse {
+    pub room_id: RoomId,
+}
+
+/// Содержимое account data **m.direct**: id пользователя -> id личных комнат с ним
+pub type DirectRoomsContent = HashMap<String, Vec<RoomId>>;
+
 pub struct Room {
     pub client: Client,
 }
@@ -37,8 +63,8 @@ impl Room {
         &self,
         room_id: &RoomId,
         device_id: &DeviceId,
-        text: String,
-    ) -> Result<EventId, Box<dyn Error>> {
+        message: &FormattedMessage,
+    ) -> Result<EventId, RequestError> {
         let mut new_uuid = Uuid::new_v4().to_string();
 
         new_uuid.push_str(device_id.0.trim());
@@ -48,25 +74,142 @@ impl Room {
             room_id.0, new_uuid
         );
 
-        let mut headers = HeaderMap::new();
-
-        headers.insert("Accept", "application/json".parse()?);
-        headers.insert(
-            "Authorization",
-            format!("Bearer {}", self.client.get_access_token().0).parse()?,
-        );
-
         let body = MessageBody {
-            body: "".to_string(),
+            body: message.body.clone(),
             msgtype: "m.text".to_string(),
             format: "org.matrix.custom.html".to_string(),
-            formatted_body: text.to_string(),
+            formatted_body: message.formatted_body.clone(),
+            mentions: Mentions {
+                user_ids: message.mentions.clone(),
+            },
         };
 
-        let response = self.client.put(path.trim(), headers, body).await?;
+        let response = self.client.put(path.trim(), body).await?;
 
-        let result = response.json::<EventId>().await?;
+        let result = response
+            .json::<EventId>()
+            .await
+            .map_err(RequestError::Decode)?;
 
         Ok(result)
     }
+
+    /// Эта функция делает **post** запрос к **/_matrix/client/v3/createRoom** для создания личной
+    /// комнаты с пользователем и приглашает его в нее
+    pub async fn create_direct_room(&self, user_id: &UserId) -> Result<RoomId, RequestError> {
+        let body = CreateRoomRequest {
+            is_direct: true,
+            invite: vec![user_id.0.clone()],
+            preset: "trusted_private_chat".to_string(),
+        };
+
+        let response = self
+            .client
+            .post("/_matrix/client/v3/createRoom", body)
+            .await?;
+
+        let result = response
+            .json::<CreateRoomResponse>()
+            .await
+            .map_err(RequestError::Decode)?;
+
+        Ok(result.room_id)
+    }
+
+    /// Эта функция делает **get** запрос к
+    /// **/_matrix/client/v3/user/{userId}/account_data/m.direct** для получения личных комнат
+    /// пользователя ```own_user_id```
+    pub async fn get_direct_rooms(
+        &self,
+        own_user_id: &UserId,
+    ) -> Result<DirectRoomsContent, RequestError> {
+        let path = format!(
+            "/_matrix/client/v3/user/{}/account_data/m.direct",
+            own_user_id.0
+        );
+
+        let response = match self.client.get(&path).await {
+            Ok(response) => response,
+            // У пользователя еще нет личных комнат
+            Err(RequestError::Server(err)) if err.status == StatusCode::NOT_FOUND => {
+                return Ok(DirectRoomsContent::new());
+            }
+            Err(err) => return Err(err),
+        };
+
+        let result = response
+            .json::<DirectRoomsContent>()
+            .await
+            .map_err(RequestError::Decode)?;
+
+        Ok(result)
+    }
+
+    /// Эта функция делает **put** запрос к
+    /// **/_matrix/client/v3/user/{userId}/account_data/m.direct** для сохранения личных комнат
+    /// пользователя ```own_user_id```
+    pub async fn set_direct_rooms(
+        &self,
+        own_user_id: &UserId,
+        content: &DirectRoomsContent,
+    ) -> Result<(), RequestError> {
+        let path = format!(
+            "/_matrix/client/v3/user/{}/account_data/m.direct",
+            own_user_id.0
+        );
+
+        self.client.put(&path, content).await?;
+
+        Ok(())
+    }
+
+    /// Функция, которая находит личную комнату с пользователем ```user_id``` в кэше или в
+    /// **m.direct**, а если ее нет, создает новую и записывает ее в **m.direct**
+    pub async fn get_or_create_direct_room(
+        &self,
+        own_user_id: &UserId,
+        user_id: &UserId,
+        direct_rooms: &mut DirectRooms,
+    ) -> Result<RoomId, SendError> {
+        if let Some(room_id) = direct_rooms.rooms.get(&user_id.0) {
+            return Ok(room_id.clone());
+        }
+
+        let mut content = self
+            .get_direct_rooms(own_user_id)
+            .await
+            .map_err(SendError::DirectRoomsData)?;
+
+        if let Some(room_id) = content.get(&user_id.0).and_then(|rooms| rooms.first()) {
+            direct_rooms
+                .rooms
+                .insert(user_id.0.clone(), room_id.clone());
+            direct_rooms.save()?;
+
+            return Ok(room_id.clone());
+        }
+
+        let room_id = self
+            .create_direct_room(user_id)
+            .await
+            .map_err(SendError::CreateDirectRoom)?;
+
+        // Комната сохраняется в кэш сразу, чтобы при ошибке записи m.direct не создавать ее снова
+        direct_rooms
+            .rooms
+            .insert(user_id.0.clone(), room_id.clone());
+        direct_rooms.save()?;
+
+        content
+            .entry(user_id.0.clone())
+            .or_default()
+            .push(room_id.clone());
+
+        // Сообщение в созданную комнату можно отправить и без записи в m.direct
+        if let Err(err) = self.set_direct_rooms(own_user_id, &content).await {
+            eprintln!("Warn: can not add room {} to m.direct: {err}", room_id.0);
+        }
+
+        Ok(room_id)
+    }
 }
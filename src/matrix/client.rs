@@ -1,160 +1,418 @@
 use std::{
-    error::Error,
-    fs::{self, File},
-    io::{BufRead, BufReader, Write},
     path::Path,
+    sync::{Arc, PoisonError, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use tokio::fs::remove_file;
+use tokio::{sync::Mutex, time};
 
-use reqwest::{Response, header::HeaderMap};
+use reqwest::{
+    RequestBuilder, Response, StatusCode,
+    header::{ACCEPT, AUTHORIZATION, HeaderValue, RETRY_AFTER},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Token, Url,
+    dotenv_vars::{env_var, optional_env_var},
+    matrix::{
+        api::{
+            self,
+            auth::{DeviceId, UserId},
+        },
+        error::{AuthError, MatrixError, RequestError, SessionError},
+        session::{SESSION_PATH, Session, SessionStore},
+    },
+    state::{read_file, remove_file},
+};
 
-use crate::{Token, Url, matrix::api};
+/// Файл с токенами открытым текстом из прошлых версий, переносится в assets/session.json
+const LEGACY_TOKENS_PATH: &str = "assets/tokens.txt";
 
 #[derive(Clone)]
 pub struct User(pub String);
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct RoomId(pub String);
 
 #[derive(Clone)]
 pub struct Password(pub String);
 
-/// Клиент для взаимодействия с api матрикса
+/// Сколько раз отправляется запрос, прежде чем вернуть ошибку
+const MAX_ATTEMPTS: u32 = 5;
+
+/// За сколько до истечения access токена он обновляется заранее (но не раньше половины срока
+/// действия токена)
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// Функция расчета, когда обновить access токен. Если срок действия токена неизвестен (сеанс
+/// прошлых версий), токен обновляется за ```REFRESH_MARGIN``` до истечения
+fn refresh_time(expires_at: SystemTime, lifetime: Option<Duration>) -> SystemTime {
+    let margin = lifetime.map_or(REFRESH_MARGIN, |lifetime| REFRESH_MARGIN.min(lifetime / 2));
+
+    expires_at - margin
+}
+
+/// Начальное и максимальное ожидание между повторами запроса
+const BASE_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Функция расчета ожидания перед повтором: экспоненциально растет с каждой попыткой и имеет
+/// случайную добавку, чтобы повторы не приходили одновременно
+fn backoff(attempt: u32) -> Duration {
+    let delay = BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt - 1))
+        .min(MAX_DELAY);
+    let jitter = rand::random_range(0..=delay.as_millis() as u64 / 2);
+
+    delay + Duration::from_millis(jitter)
+}
+
+/// Функция получения ожидания из заголовка **Retry-After** (в секундах)
+fn retry_after_header(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Сеанс бота
+struct Tokens {
+    session: Session,
+    // Когда access токен нужно обновить заранее, если известно, когда он истекает
+    refresh_at: Option<SystemTime>,
+}
+
+/// Клиент для взаимодействия с api матрикса. Копии клиента используют общие токены, поэтому
+/// обновленные токены сразу видны всем копиям
 #[derive(Clone)]
 pub struct Client {
     host: Url,
     client: reqwest::Client,
-    access_token: Token,
-    refresh_token: Token,
+    tokens: Arc<RwLock<Tokens>>,
+    // Не дает нескольким запросам обновлять токены одновременно
+    refresh_lock: Arc<Mutex<()>>,
+    store: SessionStore,
 }
 
 impl Client {
     /// Функция создания Client с пустыми токенами
-    pub fn new(host_val: Url) -> Result<Client, Box<dyn Error>> {
+    pub fn new(host_val: Url, store: SessionStore) -> Result<Client, RequestError> {
+        let session = Session {
+            homeserver: host_val.0.clone(),
+            user_id: None,
+            device_id: None,
+            access_token: Token(String::new()),
+            refresh_token: None,
+            expires_at_ms: None,
+            lifetime_ms: None,
+        };
+
+        Self::new_with_session(host_val, store, session)
+    }
+
+    /// Функция создания Client с сохраненным сеансом. Если известно, когда истекает access
+    /// токен, и есть refresh токен, то access токен обновится заранее
+    pub fn new_with_session(
+        host_val: Url,
+        store: SessionStore,
+        session: Session,
+    ) -> Result<Client, RequestError> {
+        let refresh_at = session
+            .expires_at_ms
+            .filter(|_| session.refresh_token.is_some())
+            .map(|ms| {
+                refresh_time(
+                    UNIX_EPOCH + Duration::from_millis(ms),
+                    session.lifetime_ms.map(Duration::from_millis),
+                )
+            });
+
         Ok(Self {
             host: host_val,
             client: reqwest::Client::builder().build()?,
-            access_token: Token(String::new()),
-            refresh_token: Token(String::new()),
+            tokens: Arc::new(RwLock::new(Tokens {
+                session,
+                refresh_at,
+            })),
+            refresh_lock: Arc::new(Mutex::new(())),
+            store,
         })
     }
 
-    /// Функция создания Client с токенами из файла assets/tokens.txt
-    pub fn new_from_file(host_val: Url) -> Result<Client, Box<dyn Error>> {
-        let file = File::open("assets/tokens.txt")?;
+    /// Функция создания Client с токенами из файла assets/tokens.txt прошлых версий. Вторая
+    /// строка с refresh токеном необязательна
+    fn new_from_legacy_file(host_val: Url, store: SessionStore) -> Result<Client, AuthError> {
+        let data = read_file(LEGACY_TOKENS_PATH)?;
 
-        let mut reader = BufReader::new(file).lines();
+        let mut reader = data.lines();
 
         let access_t;
-        let refresh_t;
 
-        if let Some(first_line) = reader.next() {
-            access_t = first_line?;
+        if let Some(first_line) = reader.next().filter(|line| !line.trim().is_empty()) {
+            access_t = first_line.to_string();
         } else {
-            return Err("File is empty".into());
+            return Err(AuthError::InvalidTokens("File is empty"));
         }
 
-        if let Some(second_line) = reader.next() {
-            refresh_t = second_line?;
-        } else {
-            return Err("File has only one line".into());
-        }
+        let refresh_t = reader
+            .next()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Token(line.to_string()));
 
-        Ok(Self {
-            host: host_val,
-            client: reqwest::Client::builder().build()?,
-            access_token: Token(access_t),
-            refresh_token: Token(refresh_t),
-        })
+        let client = Self::new(host_val, store).map_err(AuthError::Client)?;
+        // Срок действия сохраненного токена неизвестен, он обновится по ответу сервера
+        client.set_tokens(Token(access_t), refresh_t, None);
+
+        Ok(client)
     }
 
     pub fn get_access_token(&self) -> Token {
-        self.access_token.clone()
+        self.read_tokens(|tokens| tokens.session.access_token.clone())
+    }
+
+    pub fn get_refresh_token(&self) -> Option<Token> {
+        self.read_tokens(|tokens| tokens.session.refresh_token.clone())
+    }
+
+    fn read_tokens<T>(&self, read: impl FnOnce(&Tokens) -> T) -> T {
+        read(&self.tokens.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    fn write_tokens(&self, write: impl FnOnce(&mut Tokens)) {
+        write(&mut self.tokens.write().unwrap_or_else(PoisonError::into_inner))
     }
 
-    pub fn get_refresh_token(&self) -> Token {
-        self.refresh_token.clone()
+    /// Функция сохранения сеанса в файл assets/session.json
+    pub fn save_session(&self) -> Result<(), SessionError> {
+        let session = self.read_tokens(|tokens| tokens.session.clone());
+
+        self.store.save(&session)
+    }
+
+    /// Функция установки аккаунта и устройства бота, которым принадлежат токены
+    pub fn set_identity(&self, user_id: UserId, device_id: DeviceId) {
+        self.write_tokens(|tokens| {
+            tokens.session.user_id = Some(user_id);
+            tokens.session.device_id = Some(device_id);
+        });
     }
 
-    /// Функция сохранения токенов в файл assets/tokens.txt
-    pub fn save_tokens(&self) -> Result<&str, Box<dyn Error>> {
-        if !Path::new("assets/").exists() {
-            fs::create_dir("assets/")?;
+    /// Функция установки токенов. ```expires_in_ms``` - через сколько истечет access токен. Без
+    /// refresh токена access токен заранее не обновляется
+    pub fn set_tokens(
+        &self,
+        access_token: Token,
+        refresh_token: Option<Token>,
+        expires_in_ms: Option<i64>,
+    ) {
+        let now = SystemTime::now();
+        let lifetime = expires_in_ms.map(|ms| Duration::from_millis(ms.max(0) as u64));
+
+        let expires_at_ms = lifetime.map(|lifetime| {
+            let expires_at = now + lifetime;
+            expires_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64
+        });
+        let refresh_at = lifetime
+            .filter(|_| refresh_token.is_some())
+            .map(|lifetime| refresh_time(now + lifetime, Some(lifetime)));
+
+        self.write_tokens(|tokens| {
+            tokens.session.access_token = access_token;
+            tokens.session.refresh_token = refresh_token;
+            tokens.session.expires_at_ms = expires_at_ms;
+            tokens.session.lifetime_ms = lifetime.map(|lifetime| lifetime.as_millis() as u64);
+            tokens.refresh_at = refresh_at;
+        });
+    }
+
+    /// Функция обновления токенов через **/_matrix/client/v3/refresh** и сохранения сеанса в файл.
+    /// ```used_token``` - access токен, который не подошел. Если токены уже обновил другой
+    /// запрос, то они не обновляются повторно
+    async fn refresh_tokens(&self, used_token: &Token) -> Result<(), RequestError> {
+        let _guard = self.refresh_lock.lock().await;
+
+        if self.get_access_token().0 != used_token.0 {
+            return Ok(());
         }
 
-        let mut token_file =
-            File::create("assets/tokens.txt").expect("Error: Should be able to create file");
-        token_file
-            .write_all(format!("{}\n", self.access_token.0).as_bytes())
-            .expect("Error: Should be able to write data");
-        token_file
-            .write_all(self.refresh_token.0.as_bytes())
-            .expect("Error: Should be able to write data");
+        // Запрос обновления тоже идет через send, поэтому future нужно упаковать
+        Box::pin(self.auth().refresh())
+            .await
+            .map_err(|err| RequestError::Refresh(Box::new(err)))?;
+
+        // Токены уже обновлены в памяти, поэтому запрос можно продолжать
+        if let Err(err) = self.save_session() {
+            eprintln!("Warn: can not save refreshed matrix session: {err}");
+        }
 
-        Ok("Save tokens success")
+        println!("Matrix access token refreshed");
+        Ok(())
     }
 
-    pub fn set_tokens(&mut self, access_token: Token, refresh_token: Token) {
-        self.access_token = access_token;
-        self.refresh_token = refresh_token;
+    /// Функция получения access токена для запроса. Токен, который скоро истечет, обновляется
+    /// заранее
+    async fn fresh_access_token(&self) -> Result<Token, RequestError> {
+        let (access_token, refresh_at) =
+            self.read_tokens(|tokens| (tokens.session.access_token.clone(), tokens.refresh_at));
+
+        if refresh_at.is_some_and(|t| SystemTime::now() >= t) {
+            self.refresh_tokens(&access_token).await?;
+            return Ok(self.get_access_token());
+        }
+
+        Ok(access_token)
     }
 
-    /// Фукнция для отправки post запроса на api матрикса
+    /// Функция отправки запроса с повторами. Запрос повторяется при ошибке соединения и при
+    /// ответе **429** (с ожиданием **retry_after_ms** или **Retry-After**, если они есть), а если
+    /// ```retry_server_errors```, то и при ответе **5xx**. Между повторами ожидание растет
+    /// экспоненциально со случайной добавкой.
+    ///
+    /// Если ```authorized```, то к запросу добавляется access токен. При ответе **401
+    /// M_UNKNOWN_TOKEN** с **soft_logout** токены обновляются и запрос повторяется с новым токеном.
+    ///
+    /// Ответ с ошибкой возвращается как ```RequestError::Server```
+    async fn send(
+        &self,
+        request: RequestBuilder,
+        retry_server_errors: bool,
+        authorized: bool,
+    ) -> Result<Response, RequestError> {
+        let mut attempt = 0;
+        let mut refreshed = false;
+
+        loop {
+            attempt += 1;
+            let last_attempt = attempt >= MAX_ATTEMPTS;
+
+            // Тело запроса всегда JSON, поэтому запрос можно повторить
+            let mut current_request = request
+                .try_clone()
+                .expect("Error: Should be able to clone request");
+
+            let access_token = match authorized {
+                true => Some(self.fresh_access_token().await?),
+                false => None,
+            };
+
+            if let Some(access_token) = &access_token {
+                let header = HeaderValue::from_str(&format!("Bearer {}", access_token.0))
+                    .map_err(|_| RequestError::InvalidToken)?;
+                current_request = current_request.header(AUTHORIZATION, header);
+            }
+
+            let response = match current_request.send().await {
+                Ok(response) => response,
+                // Повторяются запросы, которые не дошли до сервера, и запросы, которые можно
+                // безопасно выполнить повторно
+                Err(err) if !last_attempt && (err.is_connect() || retry_server_errors) => {
+                    let delay = backoff(attempt);
+                    eprintln!("Warn: matrix request failed: {err}. Retry in {delay:?}");
+                    time::sleep(delay).await;
+                    continue;
+                }
+                Err(err) => return Err(RequestError::Network(err)),
+            };
+
+            let status = response.status();
+
+            if status.is_success() {
+                return Ok(response);
+            }
+
+            let retry_after = retry_after_header(&response);
+            let body = response.text().await.unwrap_or_default();
+            let error = MatrixError::from_body(status, &body);
+
+            // Истекший токен обновляется один раз, повтор с новым токеном не считается попыткой.
+            // Без refresh токена возвращается ошибка сервера
+            if let Some(access_token) = &access_token
+                && !refreshed
+                && error.is_expired_token()
+                && self.get_refresh_token().is_some()
+            {
+                eprintln!("Warn: matrix access token expired, refreshing");
+                self.refresh_tokens(access_token).await?;
+                refreshed = true;
+                attempt -= 1;
+                continue;
+            }
+
+            let retry = status == StatusCode::TOO_MANY_REQUESTS
+                || (retry_server_errors && status.is_server_error());
+
+            if !retry || last_attempt {
+                return Err(RequestError::Server(error));
+            }
+
+            let delay = match error.retry_after_ms {
+                Some(ms) => Duration::from_millis(ms),
+                None => retry_after.unwrap_or_else(|| backoff(attempt)),
+            };
+
+            eprintln!("Warn: matrix request failed: {error}. Retry in {delay:?}");
+            time::sleep(delay).await;
+        }
+    }
+
+    /// Функция создания запроса к api матрикса
+    fn request(&self, method: reqwest::Method, path: &str) -> RequestBuilder {
+        let mut url = self.host.0.clone();
+        url.push_str(path);
+
+        self.client
+            .request(method, url.trim())
+            .header(ACCEPT, HeaderValue::from_static("application/json"))
+    }
+
+    /// Фукнция для отправки post запроса на api матрикса. Ответы **5xx** не повторяются, потому
+    /// что запрос мог быть уже выполнен
     pub async fn post<T: serde::Serialize>(
         &self,
         path: &str,
-        headers: HeaderMap,
         body: T,
-    ) -> Result<Response, Box<dyn Error>> {
-        let mut url = self.host.0.clone();
-        url.push_str(path);
+    ) -> Result<Response, RequestError> {
+        let request = self.request(reqwest::Method::POST, path).json(&body);
 
-        match self
-            .client
-            .post(url.trim())
-            .headers(headers)
-            .json(&body)
-            .send()
-            .await
-        {
-            Ok(resp) => Ok(resp),
-            Err(message) => Err(Box::new(message)),
-        }
+        self.send(request, false, true).await
+    }
+
+    /// Фукнция для отправки post запроса без access токена (для входа и обновления токенов)
+    pub async fn public_post<T: serde::Serialize>(
+        &self,
+        path: &str,
+        body: T,
+    ) -> Result<Response, RequestError> {
+        let request = self.request(reqwest::Method::POST, path).json(&body);
+
+        self.send(request, false, false).await
     }
 
     /// Фукнция для отправки get запроса на api матрикса
-    pub async fn get(&self, path: &str, headers: HeaderMap) -> Result<Response, Box<dyn Error>> {
-        let mut url = self.host.0.clone();
-        url.push_str(path);
+    pub async fn get(&self, path: &str) -> Result<Response, RequestError> {
+        let request = self.request(reqwest::Method::GET, path);
 
-        match self.client.get(url.trim()).headers(headers).send().await {
-            Ok(resp) => Ok(resp),
-            Err(message) => Err(Box::new(message)),
-        }
+        self.send(request, true, true).await
     }
 
     /// Фукнция для отправки put запроса на api матрикса
     pub async fn put<T: serde::Serialize>(
         &self,
         path: &str,
-        headers: HeaderMap,
         body: T,
-    ) -> Result<Response, Box<dyn Error>> {
-        let mut url = self.host.0.clone();
-        url.push_str(path);
+    ) -> Result<Response, RequestError> {
+        let request = self.request(reqwest::Method::PUT, path).json(&body);
 
-        match self
-            .client
-            .put(url.trim())
-            .headers(headers)
-            .json(&body)
-            .send()
-            .await
-        {
-            Ok(resp) => Ok(resp),
-            Err(message) => Err(Box::new(message)),
-        }
+        self.send(request, true, true).await
     }
 
     /// Взаимодействие с auth частью api матрикса
@@ -169,63 +427,139 @@ impl Client {
 }
 
 /// Функция, которая создаст ```Client``` матрикса с access и refresh токенами. Делает login и
-/// сохраняет полученные токены
-async fn set_client_with_login(matrix_server: Url) -> Result<Client, Box<dyn Error>> {
-    let user_name = User(std::env::var("MATRIX_USER").expect("MATRIX_USER must be set in .env."));
-    let password =
-        Password(std::env::var("MATRIX_PASSWORD").expect("MATRIX_PASSWORD must be set in .env."));
+/// сохраняет полученный сеанс
+async fn set_client_with_login(
+    matrix_server: Url,
+    store: SessionStore,
+) -> Result<Client, AuthError> {
+    let user_name = User(env_var("MATRIX_USER")?);
+    let password = Password(env_var("MATRIX_PASSWORD")?);
+
+    let matrix_client = Client::new(matrix_server, store).map_err(AuthError::Client)?;
 
-    let mut matrix_client = Client::new(matrix_server)?;
+    matrix_client.auth().login(user_name, password).await?;
 
-    matrix_client = matrix_client.auth().login(user_name, password).await?;
+    matrix_client.save_session()?;
+    println!("Matrix client set");
 
-    match matrix_client.save_tokens() {
-        Ok(_) => println!("Matrix client set"),
-        Err(message) => return Err(message),
+    Ok(matrix_client)
+}
+
+/// Функция, которая создаст ```Client``` матрикса с access токеном из **MATRIX_ACCESS_TOKEN**
+/// без входа по паролю. Такой токен не обновляется и не сохраняется в файл
+async fn set_client_with_access_token(
+    matrix_server: Url,
+    store: SessionStore,
+    access_token: Token,
+) -> Result<Client, AuthError> {
+    let matrix_client = Client::new(matrix_server, store).map_err(AuthError::Client)?;
+    matrix_client.set_tokens(access_token, None, None);
+
+    let who_am_i = matrix_client.auth().who_am_i().await?;
+    matrix_client.set_identity(who_am_i.user_id, who_am_i.device_id);
+
+    println!("Matrix client set with MATRIX_ACCESS_TOKEN");
+    Ok(matrix_client)
+}
+
+/// Функция проверки валидности токенов клиента. Если сервер не принял токен, он обновляется при
+/// проверке, если есть refresh токен. Аккаунт и устройство бота берутся из ответа сервера, и сеанс сохраняется
+/// в файл
+async fn check_client(matrix_client: Client) -> Result<Client, AuthError> {
+    let who_am_i = match matrix_client.auth().who_am_i().await {
+        Ok(who_am_i) => who_am_i,
+        // Токены обновляются только если сервер их не принял, а не при ошибке сети
+        Err(err) if err.is_auth_failure() && matrix_client.get_refresh_token().is_some() => {
+            matrix_client.auth().refresh().await?;
+            matrix_client.auth().who_am_i().await?
+        }
+        Err(err) => return Err(err),
     };
 
+    matrix_client.set_identity(who_am_i.user_id, who_am_i.device_id);
+
+    matrix_client.save_session()?;
+    println!("Matrix client set");
+
     Ok(matrix_client)
 }
 
-/// Функция, которая создаст ```Client``` матрикса с access и refresh токенами. Берет токены из
-/// файла и проверяет их валидность
-async fn load_client_from_file(matrix_server: &Url) -> Result<Client, Box<dyn Error>> {
-    let mut matrix_client: Client = Client::new_from_file(matrix_server.clone())?;
+/// Функция, которая создаст ```Client``` матрикса с сеансом из файла assets/session.json
+async fn load_client_from_session(
+    matrix_server: &Url,
+    store: &SessionStore,
+) -> Result<Client, AuthError> {
+    let session = store.load()?;
 
-    if matrix_client.auth().who_am_i().await.is_ok() {
-        println!("Matrix client set");
-        return Ok(matrix_client);
+    if session.homeserver != matrix_server.0 {
+        return Err(SessionError::OtherHomeserver(session.homeserver).into());
     }
 
-    matrix_client = matrix_client.auth().refresh().await?;
+    let matrix_client = Client::new_with_session(matrix_server.clone(), store.clone(), session)
+        .map_err(AuthError::Client)?;
 
-    match matrix_client.save_tokens() {
-        Ok(_) => println!("Matrix client set"),
-        Err(message) => return Err(message),
-    };
+    check_client(matrix_client).await
+}
+
+/// Функция переноса токенов из assets/tokens.txt в assets/session.json
+async fn migrate_legacy_tokens(
+    matrix_server: &Url,
+    store: &SessionStore,
+) -> Result<Client, AuthError> {
+    let matrix_client = Client::new_from_legacy_file(matrix_server.clone(), store.clone())?;
+    let matrix_client = check_client(matrix_client).await?;
+
+    println!("Matrix tokens moved from {LEGACY_TOKENS_PATH} to {SESSION_PATH}");
     Ok(matrix_client)
 }
 
-/// Функция, которая создаст ```Client``` матрикса с access и refresh токенами. Либо берет токены
-/// из файла "assets/tokens.txt", либо делает login
-pub async fn set_client(matrix_server: Url) -> Result<Client, Box<dyn Error>> {
-    let matrix_client: Client;
-    loop {
-        if Path::new("assets/tokens.txt").exists() {
-            matrix_client = match load_client_from_file(&matrix_server).await {
-                Ok(cl) => cl,
-                Err(message) => {
-                    eprintln!("Warn: {message}");
-                    remove_file("assets/tokens.txt").await?;
-                    continue;
+/// Функция, которая создаст ```Client``` матрикса. Берет access токен из **MATRIX_ACCESS_TOKEN**,
+/// если он задан. Иначе либо берет сеанс из файла "assets/session.json" (или переносит токены из
+/// "assets/tokens.txt"), либо делает login
+pub async fn set_client(matrix_server: Url) -> Result<Client, AuthError> {
+    let store = SessionStore::from_env()?;
+
+    if let Some(access_token) = optional_env_var("MATRIX_ACCESS_TOKEN") {
+        let access_token = Token(access_token.trim().to_string());
+        return set_client_with_access_token(matrix_server, store, access_token).await;
+    }
+
+    if Path::new(LEGACY_TOKENS_PATH).exists() {
+        let migrated = match store.exists() {
+            true => None,
+            false => match migrate_legacy_tokens(&matrix_server, &store).await {
+                Ok(cl) => Some(cl),
+                Err(message) if message.is_invalid_session() => {
+                    eprintln!(
+                        "Warn: can not move matrix tokens from {LEGACY_TOKENS_PATH}: {message}"
+                    );
+                    None
                 }
-            };
-            break;
-        } else {
-            matrix_client = set_client_with_login(matrix_server).await?;
-            break;
+                // Например сервер недоступен: токены переносятся при следующем запуске
+                Err(err) => return Err(err),
+            },
+        };
+
+        // Токены открытым текстом не остаются на диске, если они перенесены или не подходят
+        remove_file(LEGACY_TOKENS_PATH)?;
+
+        if let Some(matrix_client) = migrated {
+            return Ok(matrix_client);
         }
     }
 
-    Ok(matrix_client)
+    if store.exists() {
+        match load_client_from_session(&matrix_server, &store).await {
+            Ok(cl) => return Ok(cl),
+            Err(message) if message.is_invalid_session() => {
+                eprintln!("Warn: {message}");
+                store.remove()?;
+            }
+            // Сеанс не удаляется, если его не удалось проверить (например сервер недоступен) или
+            // без ключа его не расшифровать
+            Err(err) => return Err(err),
+        }
+    }
+
+    set_client_with_login(matrix_server, store).await
 }
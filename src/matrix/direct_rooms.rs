@@ -0,0 +1,33 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    matrix::client::RoomId,
+    state::{CacheError, read_json, write_json},
+};
+
+const DIRECT_ROOMS_PATH: &str = "assets/direct_rooms.json";
+
+/// Кэш личных комнат бота с пользователями, чтобы не создавать их заново при каждом запуске
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DirectRooms {
+    // matrix_id пользователя -> id личной комнаты
+    pub rooms: HashMap<String, RoomId>,
+}
+
+impl DirectRooms {
+    /// Функция загрузки кэша из файла assets/direct_rooms.json. Если файла нет, кэш пустой
+    pub fn load() -> Result<Self, CacheError> {
+        if !Path::new(DIRECT_ROOMS_PATH).exists() {
+            return Ok(Self::default());
+        }
+
+        read_json(DIRECT_ROOMS_PATH)
+    }
+
+    /// Функция сохранения кэша в файл assets/direct_rooms.json
+    pub fn save(&self) -> Result<(), CacheError> {
+        write_json(DIRECT_ROOMS_PATH, self)
+    }
+}
@@ -0,0 +1,223 @@
+use reqwest::StatusCode;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{config::ConfigError, state::CacheError};
+
+/// Тело ответа матрикса с ошибкой
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    errcode: String,
+    #[serde(default)]
+    error: String,
+    #[serde(default)]
+    retry_after_ms: Option<u64>,
+    #[serde(default)]
+    soft_logout: bool,
+}
+
+/// Ошибка, которую вернул сервер матрикса (например **M_LIMIT_EXCEEDED** или **M_FORBIDDEN**)
+#[derive(Debug, Clone, Error)]
+#[error("{errcode} ({status}): {error}")]
+pub struct MatrixError {
+    pub status: StatusCode,
+    pub errcode: String,
+    pub error: String,
+    // Через сколько можно повторить запрос, если сервер ограничил частоту запросов
+    pub retry_after_ms: Option<u64>,
+    // Сеанс не завершен, и токены можно обновить без повторного входа
+    pub soft_logout: bool,
+}
+
+impl MatrixError {
+    /// Функция разбора ответа с ошибкой. Если тело ответа не в формате матрикса, то errcode
+    /// будет **M_UNKNOWN**, а в error будет тело ответа
+    pub fn from_body(status: StatusCode, body: &str) -> Self {
+        match serde_json::from_str::<ErrorBody>(body) {
+            Ok(body) => Self {
+                status,
+                errcode: body.errcode,
+                error: body.error,
+                retry_after_ms: body.retry_after_ms,
+                soft_logout: body.soft_logout,
+            },
+            Err(_) => Self {
+                status,
+                errcode: "M_UNKNOWN".to_string(),
+                error: body.trim().to_string(),
+                retry_after_ms: None,
+                soft_logout: false,
+            },
+        }
+    }
+
+    /// Функция проверки, что access токен истек и его можно обновить
+    pub fn is_expired_token(&self) -> bool {
+        self.status == StatusCode::UNAUTHORIZED
+            && self.errcode == "M_UNKNOWN_TOKEN"
+            && self.soft_logout
+    }
+
+    /// Функция проверки, что сервер не принял access токен
+    pub fn is_auth_failure(&self) -> bool {
+        matches!(self.errcode.as_str(), "M_UNKNOWN_TOKEN" | "M_MISSING_TOKEN")
+    }
+
+    /// Функция проверки, что повтор запроса вернет ту же ошибку (например бот не состоит в
+    /// комнате или комнаты нет). Ограничение частоты запросов и ошибки токенов к ним не относятся
+    pub fn is_permanent(&self) -> bool {
+        self.status.is_client_error()
+            && self.status != StatusCode::TOO_MANY_REQUESTS
+            && !self.is_auth_failure()
+    }
+}
+
+/// Ошибка запроса к api матрикса
+#[derive(Debug, Error)]
+pub enum RequestError {
+    // Запрос не дошел до сервера или ответ не был получен
+    #[error("matrix request failed: {0}")]
+    Network(#[from] reqwest::Error),
+    #[error(transparent)]
+    Server(#[from] MatrixError),
+    #[error("invalid matrix response: {0}")]
+    Decode(reqwest::Error),
+    #[error("access token can not be used in the Authorization header")]
+    InvalidToken,
+    // Токен истек, но обновить его не удалось
+    #[error("{0}")]
+    Refresh(Box<AuthError>),
+}
+
+impl RequestError {
+    /// Функция проверки, что запрос не выполнен из-за токенов бота, и без повторного входа
+    /// запросы не будут выполняться
+    pub fn is_auth_failure(&self) -> bool {
+        match self {
+            Self::Server(err) => err.is_auth_failure(),
+            Self::InvalidToken => true,
+            Self::Refresh(err) => err.is_auth_failure(),
+            Self::Network(_) | Self::Decode(_) => false,
+        }
+    }
+
+    /// Функция проверки, что сервер отклонил запрос, и повторять его бесполезно
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            Self::Server(err) => err.is_permanent(),
+            Self::Network(_) | Self::Decode(_) | Self::InvalidToken | Self::Refresh(_) => false,
+        }
+    }
+}
+
+/// Ошибка входа в аккаунт бота и работы с его токенами
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("can not create matrix client: {0}")]
+    Client(RequestError),
+    #[error("matrix login failed: {0}")]
+    Login(RequestError),
+    #[error("matrix token refresh failed: {0}")]
+    Refresh(RequestError),
+    #[error("matrix access token can not be refreshed: the server did not issue a refresh token")]
+    NoRefreshToken,
+    #[error("matrix session check failed: {0}")]
+    WhoAmI(RequestError),
+    #[error("invalid tokens file: {0}")]
+    InvalidTokens(&'static str),
+    #[error(transparent)]
+    Tokens(#[from] CacheError),
+    #[error(transparent)]
+    Session(#[from] SessionError),
+}
+
+/// Ошибка файла с сеансом бота
+#[derive(Debug, Error)]
+pub enum SessionError {
+    #[error(transparent)]
+    File(#[from] CacheError),
+    #[error("invalid session file: {0}")]
+    Invalid(serde_json::Error),
+    #[error("session file is encrypted, but MATRIX_SESSION_KEY is not set")]
+    MissingKey,
+    // Ключ не тот, которым файл был зашифрован, или файл поврежден
+    #[error("can not decrypt session file, MATRIX_SESSION_KEY may be wrong")]
+    Decrypt,
+    #[error("can not encrypt session")]
+    Encrypt,
+    #[error("session belongs to {0}, but MATRIX_SERVER is different")]
+    OtherHomeserver(String),
+}
+
+impl AuthError {
+    /// Функция проверки, что сервер не принял токены бота, и нужен повторный вход. Ошибки сети и
+    /// сервера к ним не относятся, запрос можно повторить позже
+    pub fn is_auth_failure(&self) -> bool {
+        match self {
+            Self::Client(err) | Self::Login(err) | Self::Refresh(err) | Self::WhoAmI(err) => {
+                err.is_auth_failure()
+            }
+            Self::NoRefreshToken => true,
+            Self::Config(_) | Self::InvalidTokens(_) | Self::Tokens(_) | Self::Session(_) => false,
+        }
+    }
+
+    /// Функция проверки, что сохраненные токены нельзя использовать: сервер их не принял, или
+    /// файл с ними поврежден. Тогда нужен повторный вход
+    pub fn is_invalid_session(&self) -> bool {
+        match self {
+            Self::InvalidTokens(_)
+            | Self::Tokens(CacheError::Invalid { .. })
+            | Self::Session(
+                SessionError::Invalid(_)
+                | SessionError::Decrypt
+                | SessionError::OtherHomeserver(_)
+                | SessionError::File(CacheError::Invalid { .. }),
+            ) => true,
+            _ => self.is_auth_failure(),
+        }
+    }
+}
+
+/// Ошибка отправки сообщения в комнату
+#[derive(Debug, Error)]
+pub enum SendError {
+    #[error("no template for \"{0}\" notification")]
+    MissingTemplate(String),
+    #[error(transparent)]
+    Request(#[from] RequestError),
+    #[error("can not create direct room: {0}")]
+    CreateDirectRoom(RequestError),
+    #[error("can not get or set m.direct account data: {0}")]
+    DirectRoomsData(RequestError),
+    #[error(transparent)]
+    DirectRooms(#[from] CacheError),
+    #[error("{0} notifications were not sent")]
+    NotSent(usize),
+}
+
+impl SendError {
+    /// Функция проверки, что сообщение не отправлено из-за токенов бота
+    pub fn is_auth_failure(&self) -> bool {
+        match self {
+            Self::Request(err) | Self::CreateDirectRoom(err) | Self::DirectRoomsData(err) => {
+                err.is_auth_failure()
+            }
+            Self::MissingTemplate(_) | Self::DirectRooms(_) | Self::NotSent(_) => false,
+        }
+    }
+
+    /// Функция проверки, что сообщение не будет отправлено и при следующей попытке
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            Self::Request(err) | Self::CreateDirectRoom(err) | Self::DirectRoomsData(err) => {
+                err.is_permanent()
+            }
+            // Шаблоны загружаются только при запуске
+            Self::MissingTemplate(_) => true,
+            Self::DirectRooms(_) | Self::NotSent(_) => false,
+        }
+    }
+}
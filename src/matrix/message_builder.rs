@@ -0,0 +1,93 @@
+/// Сообщение в двух видах: простой текст для клиентов без поддержки HTML и HTML разметка
+#[derive(Debug, Clone, Default)]
+pub struct FormattedMessage {
+    pub body: String,
+    pub formatted_body: String,
+    // matrix_id упомянутых в сообщении пользователей
+    pub mentions: Vec<String>,
+}
+
+/// Построитель сообщения, который одновременно собирает простой текст и экранированный HTML
+#[derive(Debug, Default)]
+pub struct MessageBuilder {
+    message: FormattedMessage,
+}
+
+impl MessageBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Добавляет обычный текст. Переносы строк становятся ```<br>```
+    pub fn text(&mut self, text: &str) -> &mut Self {
+        self.message.body.push_str(text);
+        self.message
+            .formatted_body
+            .push_str(&escape_html(text).replace('\n', "<br>"));
+        self
+    }
+
+    /// Начинает текст, выделенный жирным
+    pub fn start_bold(&mut self) -> &mut Self {
+        self.message.formatted_body.push_str("<strong>");
+        self
+    }
+
+    /// Заканчивает текст, выделенный жирным
+    pub fn end_bold(&mut self) -> &mut Self {
+        self.message.formatted_body.push_str("</strong>");
+        self
+    }
+
+    /// Добавляет ссылку. В простом тексте ссылка пишется в скобках после текста
+    pub fn link(&mut self, text: &str, url: &str) -> &mut Self {
+        if text == url {
+            self.message.body.push_str(url);
+        } else {
+            self.message.body.push_str(&format!("{text} ({url})"));
+        }
+        self.message.formatted_body.push_str(&format!(
+            "<a href=\"{}\">{}</a>",
+            escape_html(url),
+            escape_html(text)
+        ));
+        self
+    }
+
+    /// Добавляет упоминание пользователя в виде "пилюли" и запоминает его для **m.mentions**
+    pub fn mention(&mut self, matrix_id: &str, display_name: &str) -> &mut Self {
+        self.message.body.push_str(display_name);
+        self.message.formatted_body.push_str(&format!(
+            "<a href=\"https://matrix.to/#/{}\">{}</a>",
+            escape_html(matrix_id),
+            escape_html(display_name)
+        ));
+
+        if !self.message.mentions.iter().any(|m| m == matrix_id) {
+            self.message.mentions.push(matrix_id.to_string());
+        }
+        self
+    }
+
+    pub fn build(&self) -> FormattedMessage {
+        self.message.clone()
+    }
+}
+
+/// Экранирует символы, которые имеют особое значение в HTML
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
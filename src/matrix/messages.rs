@@ -1,110 +1,388 @@
-use std::error::Error;
+use chrono::Utc;
 
 use crate::{
     anytype::{
-        entities::notification::{AnytypeToMatrixIdMap, NotificationObject},
-        parser::find_matrix_user_id,
+        entities::{
+            api_response::Property,
+            notification::{AnytypeToMatrixIdMap, NotificationObject, NotificationType},
+        },
+        parser::{find_display_name, find_matrix_user_id},
     },
+    config::AppConfig,
+    locale::Locale,
     matrix::{
         api::auth::DeviceId,
         client::{Client, RoomId},
+        error::SendError,
+        message_builder::{FormattedMessage, MessageBuilder},
     },
+    templates::{Node, Templates},
 };
 
-fn format_notify_message(
-    notification: NotificationObject,
+/// Value of a template placeholder
+enum Value {
+    Text(String),
+    // Anytype space member ids
+    Users(Vec<String>),
+    Link(String),
+    Flag(bool),
+    // Value is not set, but has a localized placeholder text, e.g. "<no deadline>"
+    Missing(String),
+    Empty,
+}
+
+impl Value {
+    /// Check if the {{#if}} block with this value is rendered
+    fn is_set(&self) -> bool {
+        match self {
+            Value::Text(text) => !text.is_empty(),
+            Value::Users(users) => !users.is_empty(),
+            Value::Link(_) => true,
+            Value::Flag(flag) => *flag,
+            Value::Missing(_) | Value::Empty => false,
+        }
+    }
+}
+
+/// Add users as Matrix mention pills (or as Anytype display names if they have no Matrix ID)
+fn mention_users(
+    message: &mut MessageBuilder,
+    users: &[String],
+    matrix_id_map: &AnytypeToMatrixIdMap,
+    locale: &Locale,
+) {
+    if users.is_empty() {
+        message.text(&locale.text("no_users"));
+        return;
+    }
+
+    for (i, u) in users.iter().enumerate() {
+        if i > 0 {
+            message.text(", ");
+        }
+
+        match find_matrix_user_id(matrix_id_map, u) {
+            Some(matrix_id) => {
+                // Show the Anytype name if it is known, otherwise the Matrix ID
+                let text = match matrix_id_map.names.get(u) {
+                    Some(name) => name.clone(),
+                    None => matrix_id.clone(),
+                };
+
+                message.mention(&matrix_id, &text)
+            }
+            None => message.text(
+                &find_display_name(matrix_id_map, u).unwrap_or_else(|| locale.text("unknown_user")),
+            ),
+        };
+    }
+}
+
+/// Get the value of the object property for {{property.<key>}}
+fn property_value(properties: &[Property], key: &str, locale: &Locale) -> Value {
+    let Some(p) = properties.iter().find(|p| p.key == key) else {
+        return Value::Empty;
+    };
+
+    if let Some(objects) = &p.objects {
+        return Value::Users(objects.clone());
+    }
+
+    if let Some(checkbox) = p.checkbox {
+        return Value::Flag(checkbox);
+    }
+
+    if let Some(number) = p.number {
+        return Value::Text(number.to_string());
+    }
+
+    if let Some(date) = &p.date {
+        return Value::Text(locale.format_date(date));
+    }
+
+    if let Some(select) = &p.select {
+        return Value::Text(select.name.clone());
+    }
+
+    if let Some(multi_select) = &p.multi_select {
+        return Value::Text(
+            multi_select
+                .iter()
+                .map(|s| s.name.clone())
+                .collect::<Vec<String>>()
+                .join(", "),
+        );
+    }
+
+    match p
+        .text
+        .as_ref()
+        .or(p.url.as_ref())
+        .or(p.email.as_ref())
+        .or(p.phone.as_ref())
+    {
+        Some(text) => Value::Text(text.clone()),
+        None => Value::Empty,
+    }
+}
+
+/// Get the value of the placeholder from the notification
+fn placeholder_value(
+    name: &str,
+    notification: &NotificationObject,
+    config: &AppConfig,
+    locale: &Locale,
+) -> Value {
+    if let Some(key) = name.strip_prefix("property.") {
+        return property_value(&notification.properties, key, locale);
+    }
+
+    let text = |text: &str| Value::Text(text.to_string());
+    let optional = |value: &Option<String>| match value {
+        Some(value) => Value::Text(value.clone()),
+        None => Value::Empty,
+    };
+    let date = |value: &Option<String>| match value {
+        Some(value) => Value::Text(locale.format_date(value)),
+        None => Value::Empty,
+    };
+    let missing = |key: &str| Value::Missing(locale.text(key));
+
+    match (name, &notification.notification_type) {
+        ("id", _) => text(&notification.id),
+        ("space_id", _) => text(&notification.space_id),
+        ("type_key", _) => optional(&notification.type_key),
+        ("name", _) => text(&notification.name),
+        ("snippet", _) => match &notification.snippet {
+            Some(snippet) => text(snippet),
+            None => missing("no_snippet"),
+        },
+        ("creation_date", _) => match &notification.creation_date {
+            Some(date) => Value::Text(locale.format_datetime(date)),
+            None => missing("no_creation_date"),
+        },
+        ("due_date", _) => match &notification.due_date {
+            Some(due_date) => Value::Text(locale.format_date(due_date)),
+            None => missing("no_deadline"),
+        },
+        ("assignee", _) => Value::Users(notification.assignee.clone()),
+        ("proposed_by", _) => Value::Users(notification.proposed_by.clone()),
+        ("link", _) => notification.deep_link().map_or(Value::Empty, Value::Link),
+        ("web_link", _) => config
+            .web_url_template
+            .as_deref()
+            .and_then(|template| notification.web_link(template))
+            .map_or(Value::Empty, Value::Link),
+        ("added", NotificationType::AssigneeChanged { added, .. }) => Value::Users(added.clone()),
+        ("removed", NotificationType::AssigneeChanged { removed, .. }) => {
+            Value::Users(removed.clone())
+        }
+        ("old_due_date", NotificationType::DeadlineChanged { old, .. }) => date(old),
+        ("new_due_date", NotificationType::DeadlineChanged { new, .. }) => date(new),
+        ("old_status", NotificationType::StatusChanged { old, .. }) => optional(old),
+        ("new_status", NotificationType::StatusChanged { new, .. }) => optional(new),
+        ("done", NotificationType::StatusChanged { done, .. }) => Value::Flag(*done),
+        ("missed", NotificationType::Overdue { missed, .. }) => Value::Text(missed.to_string()),
+        ("escalate", NotificationType::Overdue { escalate, .. }) => Value::Flag(*escalate),
+        _ => Value::Empty,
+    }
+}
+
+/// Render template nodes to the message
+fn render_nodes(
+    nodes: &[Node],
+    notification: &NotificationObject,
+    matrix_id_map: &AnytypeToMatrixIdMap,
+    config: &AppConfig,
+    locale: &Locale,
+    message: &mut MessageBuilder,
+    bold: &mut bool,
+) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => {
+                message.text(text);
+            }
+            Node::Bold => {
+                if *bold {
+                    message.end_bold();
+                } else {
+                    message.start_bold();
+                }
+                *bold = !*bold;
+            }
+            Node::Placeholder { name, label } => {
+                match placeholder_value(name, notification, config, locale) {
+                    Value::Text(text) | Value::Missing(text) => {
+                        message.text(&text);
+                    }
+                    Value::Users(users) => mention_users(message, &users, matrix_id_map, locale),
+                    Value::Link(url) => {
+                        message.link(label.as_deref().unwrap_or(&url), &url);
+                    }
+                    Value::Flag(flag) => {
+                        message.text(&locale.text(if flag { "yes" } else { "no" }));
+                    }
+                    Value::Empty => {}
+                }
+            }
+            Node::If {
+                name,
+                then,
+                otherwise,
+            } => {
+                let nodes = if placeholder_value(name, notification, config, locale).is_set() {
+                    then
+                } else {
+                    otherwise
+                };
+
+                render_nodes(
+                    nodes,
+                    notification,
+                    matrix_id_map,
+                    config,
+                    locale,
+                    message,
+                    bold,
+                );
+            }
+        }
+    }
+}
+
+/// Format the notification with the template of its type
+fn format_message(
+    notification: &NotificationObject,
     matrix_id_map: &AnytypeToMatrixIdMap,
-) -> String {
-    let name = notification.name;
-    let snippet = notification.snippet;
-    let creation_date = notification.creation_date;
-    let due_date = notification.due_date;
-
-    // Get matrix user ids using mapping
-    let assignee = notification
-        .assignee
-        .iter()
-        .map(|a| find_matrix_user_id(matrix_id_map, a.as_str()))
-        .collect::<Vec<String>>()
-        .join(", ");
-
-    let proposed_by = notification
-        .proposed_by
-        .iter()
-        .map(|p| find_matrix_user_id(matrix_id_map, p.as_str()))
-        .collect::<Vec<String>>()
-        .join(", ");
-
-    format!(
-        "От {proposed_by} поступила новая задача:\n{name}\n\n{snippet}\n\n{assignee}\n\nДата создания: {creation_date}\nДедлайн: {due_date}",
-    )
+    templates: &Templates,
+    config: &AppConfig,
+) -> Result<FormattedMessage, SendError> {
+    let template = templates
+        .get(&notification.notification_type)
+        .ok_or_else(|| {
+            SendError::MissingTemplate(notification.notification_type.template_key().to_string())
+        })?;
+
+    let mut message = MessageBuilder::new();
+    let mut bold = false;
+
+    render_nodes(
+        &template.nodes,
+        notification,
+        matrix_id_map,
+        config,
+        &templates.locale,
+        &mut message,
+        &mut bold,
+    );
+
+    // Close the bold text if the template didn't
+    if bold {
+        message.end_bold();
+    }
+
+    Ok(message.build())
 }
 
+/// Format the notification with its template and send it to the room
 pub async fn send_message(
-    notification: NotificationObject,
+    notification: &NotificationObject,
     matrix_id_map: &AnytypeToMatrixIdMap,
+    templates: &Templates,
+    config: &AppConfig,
     matrix_client: &Client,
     room_id: &RoomId,
     device_id: &DeviceId,
-) -> Result<(), Box<dyn Error>> {
-    let message = format_notify_message(notification, matrix_id_map);
+) -> Result<(), SendError> {
+    let message = format_message(notification, matrix_id_map, templates, config)?;
 
     matrix_client
         .room()
-        .send_message(room_id, device_id, message.clone())
+        .send_message(room_id, device_id, &message)
         .await?;
 
     println!("Notification text:");
-    println!("{message}");
+    println!("{}", message.body);
     println!();
     Ok(())
 }
 
-fn format_renotify_message(
-    notification: NotificationObject,
+/// Add the group of the digest tasks with its title, empty groups are skipped
+fn add_digest_group(
+    message: &mut MessageBuilder,
+    title: &str,
+    tasks: &[NotificationObject],
     matrix_id_map: &AnytypeToMatrixIdMap,
-) -> String {
-    let name = notification.name;
-    let snippet = notification.snippet;
-    let creation_date = notification.creation_date;
-    let due_date = notification.due_date;
-
-    // Get matrix user ids using mapping
-    let assignee = notification
-        .assignee
-        .iter()
-        .map(|a| find_matrix_user_id(matrix_id_map, a.as_str()))
-        .collect::<Vec<String>>()
-        .join(", ");
-
-    let proposed_by = notification
-        .proposed_by
-        .iter()
-        .map(|p| find_matrix_user_id(matrix_id_map, p.as_str()))
-        .collect::<Vec<String>>()
-        .join(", ");
-
-    format!(
-        "От {proposed_by} повторное уведомление о задаче:\n{name}\n\n{snippet}\n\n{assignee}\n\nДата создания: {creation_date}\nДедлайн: {due_date}",
-    )
+    locale: &Locale,
+) {
+    if tasks.is_empty() {
+        return;
+    }
+
+    message
+        .text("\n\n")
+        .start_bold()
+        .text(&format!("{title} ({})", tasks.len()))
+        .end_bold();
+
+    for task in tasks {
+        message.text("\n- ");
+
+        match task.deep_link() {
+            Some(link) => message.link(&task.name, &link),
+            None => message.text(&task.name),
+        };
+
+        if let Some(due_date) = &task.due_date {
+            message.text(&format!(" ({})", locale.format_date(due_date)));
+        }
+
+        if !task.assignee.is_empty() {
+            message.text(" - ");
+            mention_users(message, &task.assignee, matrix_id_map, locale);
+        }
+    }
 }
 
-pub async fn send_renotify_message(
-    notification: NotificationObject,
+/// Format the digest with the groups of tasks and send it to the room
+pub async fn send_digest(
+    title_key: &str,
+    groups: &[(&str, &[NotificationObject])],
     matrix_id_map: &AnytypeToMatrixIdMap,
+    templates: &Templates,
     matrix_client: &Client,
     room_id: &RoomId,
     device_id: &DeviceId,
-) -> Result<(), Box<dyn Error>> {
-    let message = format_renotify_message(notification, matrix_id_map);
+) -> Result<(), SendError> {
+    let locale = &templates.locale;
+    let mut message = MessageBuilder::new();
+
+    let today = locale.format_date(&Utc::now().to_rfc3339());
+    message
+        .start_bold()
+        .text(&locale.text(title_key).replace("{date}", &today))
+        .end_bold();
+
+    for (title, tasks) in groups {
+        add_digest_group(
+            &mut message,
+            &locale.text(title),
+            tasks,
+            matrix_id_map,
+            locale,
+        );
+    }
+
+    let message = message.build();
 
     matrix_client
         .room()
-        .send_message(room_id, device_id, message.clone())
+        .send_message(room_id, device_id, &message)
         .await?;
 
-    println!("Notification text:");
-    println!("{message}");
+    println!("Digest text:");
+    println!("{}", message.body);
     println!();
     Ok(())
 }
@@ -1,3 +1,7 @@
-mod api;
+pub mod api;
 pub mod client;
+pub mod direct_rooms;
+pub mod error;
+pub mod message_builder;
 pub mod messages;
+pub mod session;
@@ -0,0 +1,266 @@
+use std::path::Path;
+
+use aes_gcm::{
+    Aes256Gcm, Key, Nonce,
+    aead::{Aead, KeyInit},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Token,
+    config::ConfigError,
+    dotenv_vars::optional_env_var,
+    matrix::{
+        api::auth::{DeviceId, UserId},
+        error::SessionError,
+    },
+    state::{CacheError, read_json, remove_file, write_private_file},
+};
+
+pub const SESSION_PATH: &str = "assets/session.json";
+
+/// Переменная окружения с ключом шифрования файла сеанса (32 байта в hex)
+const SESSION_KEY_VAR: &str = "MATRIX_SESSION_KEY";
+
+/// Сеанс бота, который сохраняется между перезапусками
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Session {
+    // Сервер, на котором создан сеанс (MATRIX_SERVER)
+    pub homeserver: String,
+    // Неизвестны, пока сервер их не сообщил (например после переноса tokens.txt)
+    pub user_id: Option<UserId>,
+    pub device_id: Option<DeviceId>,
+    pub access_token: Token,
+    // Нет, если сервер выдает только долгоживущие access токены
+    #[serde(default)]
+    pub refresh_token: Option<Token>,
+    // Когда истекает access токен (unix время в миллисекундах), если это известно
+    pub expires_at_ms: Option<u64>,
+    // Срок действия access токена в миллисекундах. Нет в файлах прошлых версий
+    #[serde(default)]
+    pub lifetime_ms: Option<u64>,
+}
+
+/// Содержимое файла сеанса: сеанс открытым текстом или зашифрованный сеанс
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "format", rename_all = "lowercase")]
+enum SessionFile {
+    Plain { session: Session },
+    // Сеанс в JSON, зашифрованный AES-256-GCM. Значения в hex
+    Encrypted { nonce: String, ciphertext: String },
+}
+
+/// Хранилище сеанса в файле assets/session.json. Файл доступен только владельцу, а если задан
+/// **MATRIX_SESSION_KEY**, то еще и зашифрован
+#[derive(Clone)]
+pub struct SessionStore {
+    path: String,
+    key: Option<[u8; 32]>,
+}
+
+impl SessionStore {
+    /// Функция создания хранилища с ключом из **MATRIX_SESSION_KEY**. Если переменная не задана,
+    /// сеанс хранится без шифрования
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let Some(value) = optional_env_var(SESSION_KEY_VAR) else {
+            return Ok(Self {
+                path: SESSION_PATH.to_string(),
+                key: None,
+            });
+        };
+
+        let mut key = [0u8; 32];
+        hex::decode_to_slice(value.trim(), &mut key).map_err(|_| {
+            ConfigError::Invalid(format!(
+                "{SESSION_KEY_VAR} must be 64 hex characters (32 bytes)"
+            ))
+        })?;
+
+        Ok(Self {
+            path: SESSION_PATH.to_string(),
+            key: Some(key),
+        })
+    }
+
+    pub fn exists(&self) -> bool {
+        Path::new(&self.path).exists()
+    }
+
+    /// Функция загрузки сеанса из файла
+    pub fn load(&self) -> Result<Session, SessionError> {
+        match read_json::<SessionFile>(&self.path)? {
+            SessionFile::Plain { session } => Ok(session),
+            SessionFile::Encrypted { nonce, ciphertext } => {
+                let key = self.key.as_ref().ok_or(SessionError::MissingKey)?;
+
+                let nonce = hex::decode(nonce).map_err(|_| SessionError::Decrypt)?;
+                let ciphertext = hex::decode(ciphertext).map_err(|_| SessionError::Decrypt)?;
+
+                if nonce.len() != 12 {
+                    return Err(SessionError::Decrypt);
+                }
+
+                let data = cipher(key)
+                    .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+                    .map_err(|_| SessionError::Decrypt)?;
+
+                serde_json::from_slice(&data).map_err(SessionError::Invalid)
+            }
+        }
+    }
+
+    /// Функция сохранения сеанса в файл. Файл заменяется целиком и доступен только владельцу
+    pub fn save(&self, session: &Session) -> Result<(), SessionError> {
+        let file = match &self.key {
+            None => SessionFile::Plain {
+                session: session.clone(),
+            },
+            Some(key) => {
+                let data = serde_json::to_vec(session).map_err(SessionError::Invalid)?;
+                let nonce: [u8; 12] = rand::random();
+
+                let ciphertext = cipher(key)
+                    .encrypt(Nonce::from_slice(&nonce), data.as_ref())
+                    .map_err(|_| SessionError::Encrypt)?;
+
+                SessionFile::Encrypted {
+                    nonce: hex::encode(nonce),
+                    ciphertext: hex::encode(ciphertext),
+                }
+            }
+        };
+
+        let data = serde_json::to_vec_pretty(&file).map_err(SessionError::Invalid)?;
+
+        Ok(write_private_file(&self.path, &data)?)
+    }
+
+    pub fn remove(&self) -> Result<(), CacheError> {
+        remove_file(&self.path)
+    }
+}
+
+fn cipher(key: &[u8; 32]) -> Aes256Gcm {
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Временный файл сеанса, который удаляется после теста
+    struct TempPath(String);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "anytype-bot-{name}-{}-{}.json",
+                std::process::id(),
+                rand::random::<u32>()
+            ));
+
+            Self(path.to_string_lossy().into_owned())
+        }
+
+        fn store(&self, key: Option<[u8; 32]>) -> SessionStore {
+            SessionStore {
+                path: self.0.clone(),
+                key,
+            }
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn session() -> Session {
+        Session {
+            homeserver: "https://matrix.example.org".to_string(),
+            user_id: Some(UserId("@bot:example.org".to_string())),
+            device_id: Some(DeviceId("DEVICE".to_string())),
+            access_token: Token("access".to_string()),
+            refresh_token: Some(Token("refresh".to_string())),
+            expires_at_ms: Some(1_700_000_000_000),
+            lifetime_ms: Some(300_000),
+        }
+    }
+
+    fn assert_same(loaded: &Session, expected: &Session) {
+        assert_eq!(
+            serde_json::to_value(loaded).unwrap(),
+            serde_json::to_value(expected).unwrap()
+        );
+    }
+
+    #[test]
+    fn plain_round_trip() {
+        let path = TempPath::new("plain");
+        let store = path.store(None);
+
+        assert!(!store.exists());
+        store.save(&session()).unwrap();
+        assert!(store.exists());
+
+        assert_same(&store.load().unwrap(), &session());
+    }
+
+    #[test]
+    fn encrypted_round_trip() {
+        let path = TempPath::new("encrypted");
+        let store = path.store(Some([7; 32]));
+
+        store.save(&session()).unwrap();
+
+        let contents = std::fs::read_to_string(&path.0).unwrap();
+        assert!(contents.contains("\"encrypted\""));
+        assert!(!contents.contains("access"));
+
+        assert_same(&store.load().unwrap(), &session());
+    }
+
+    #[test]
+    fn wrong_key() {
+        let path = TempPath::new("wrong-key");
+        path.store(Some([7; 32])).save(&session()).unwrap();
+
+        let result = path.store(Some([8; 32])).load();
+        assert!(matches!(result, Err(SessionError::Decrypt)));
+    }
+
+    #[test]
+    fn missing_key() {
+        let path = TempPath::new("missing-key");
+        path.store(Some([7; 32])).save(&session()).unwrap();
+
+        let result = path.store(None).load();
+        assert!(matches!(result, Err(SessionError::MissingKey)));
+    }
+
+    #[test]
+    fn remove() {
+        let path = TempPath::new("remove");
+        let store = path.store(None);
+
+        store.save(&session()).unwrap();
+        store.remove().unwrap();
+        assert!(!store.exists());
+
+        // Удаление отсутствующего файла не является ошибкой
+        store.remove().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = TempPath::new("private");
+        path.store(None).save(&session()).unwrap();
+
+        let mode = std::fs::metadata(&path.0).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
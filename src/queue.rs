@@ -0,0 +1,176 @@
+use crate::{
+    anytype::entities::notification::{NotificationObject, NotificationType},
+    config::QuietHoursConfig,
+    matrix::client::RoomId,
+    state::{CacheError, read_json, write_json},
+};
+
+use chrono::{DateTime, Datelike};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const QUEUE_PATH: &str = "assets/queue.json";
+
+impl QuietHoursConfig {
+    /// Check if notifications must not be sent at this time
+    pub fn is_quiet(&self, now: DateTime<Tz>) -> bool {
+        if !self.working_days.contains(&now.weekday()) {
+            return true;
+        }
+
+        let (Some(start), Some(end)) = (self.start, self.end) else {
+            return false;
+        };
+
+        let time = now.time();
+
+        if start <= end {
+            start <= time && time < end
+        } else {
+            // The quiet time goes over midnight
+            time >= start || time < end
+        }
+    }
+
+    /// Check if the notification is sent during the quiet time too
+    pub fn bypasses(&self, notification: &NotificationObject) -> bool {
+        let key = notification.notification_type.template_key();
+        self.bypass.iter().any(|b| b == key)
+    }
+}
+
+impl NotificationType {
+    /// Reminders are repeated, so only the last one is worth sending
+    pub fn is_reminder(&self) -> bool {
+        matches!(
+            self,
+            NotificationType::Unassigned
+                | NotificationType::UpcomingDeadline
+                | NotificationType::Overdue { .. }
+        )
+    }
+}
+
+/// Notification waiting to be sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedNotification {
+    #[serde(flatten)]
+    pub notification: NotificationObject,
+    // Rooms where the notification wasn't sent yet, all matching rooms if None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rooms: Option<Vec<RoomId>>,
+}
+
+/// Notifications postponed until the quiet time ends or not sent to some rooms,
+/// kept between restarts
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct NotificationQueue {
+    pub objects: Vec<QueuedNotification>,
+}
+
+impl NotificationQueue {
+    /// Load the queue from assets/queue.json, the queue is empty if there is no file
+    pub fn load() -> Result<Self, CacheError> {
+        if !Path::new(QUEUE_PATH).exists() {
+            return Ok(Self::default());
+        }
+
+        read_json(QUEUE_PATH)
+    }
+
+    /// Save the queue to assets/queue.json
+    pub fn save(&self) -> Result<(), CacheError> {
+        write_json(QUEUE_PATH, self)
+    }
+
+    /// Add the notification for the rooms (all matching rooms if None),
+    /// the queued reminder of the same type about the object is replaced
+    pub fn push(&mut self, notification: NotificationObject, rooms: Option<Vec<RoomId>>) {
+        if notification.notification_type.is_reminder() {
+            let key = notification.notification_type.template_key();
+            self.objects.retain(|o| {
+                o.notification.id != notification.id
+                    || o.notification.notification_type.template_key() != key
+            });
+        }
+
+        self.objects.push(QueuedNotification {
+            notification,
+            rooms,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveTime, TimeZone, Weekday};
+
+    fn time(hour: u32, minute: u32) -> Option<NaiveTime> {
+        NaiveTime::from_hms_opt(hour, minute, 0)
+    }
+
+    /// Time in Moscow on the days of the week starting from Monday, January 5, 2026
+    fn at(weekday: Weekday, hour: u32, minute: u32) -> DateTime<Tz> {
+        let day = 5 + weekday.num_days_from_monday();
+        chrono_tz::Europe::Moscow
+            .with_ymd_and_hms(2026, 1, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn quiet_hours(start: Option<NaiveTime>, end: Option<NaiveTime>) -> QuietHoursConfig {
+        QuietHoursConfig {
+            start,
+            end,
+            working_days: vec![
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri,
+            ],
+            bypass: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn quiet_within_the_same_day() {
+        let config = quiet_hours(time(13, 0), time(14, 0));
+
+        assert!(!config.is_quiet(at(Weekday::Mon, 12, 59)));
+        assert!(config.is_quiet(at(Weekday::Mon, 13, 0)));
+        assert!(config.is_quiet(at(Weekday::Mon, 13, 59)));
+        assert!(!config.is_quiet(at(Weekday::Mon, 14, 0)));
+    }
+
+    #[test]
+    fn quiet_over_midnight() {
+        let config = quiet_hours(time(22, 0), time(8, 0));
+
+        assert!(!config.is_quiet(at(Weekday::Tue, 21, 59)));
+        assert!(config.is_quiet(at(Weekday::Tue, 22, 0)));
+        assert!(config.is_quiet(at(Weekday::Wed, 0, 0)));
+        assert!(config.is_quiet(at(Weekday::Wed, 7, 59)));
+        assert!(!config.is_quiet(at(Weekday::Wed, 8, 0)));
+        assert!(!config.is_quiet(at(Weekday::Wed, 12, 0)));
+    }
+
+    #[test]
+    fn quiet_on_non_working_days() {
+        let config = quiet_hours(time(22, 0), time(8, 0));
+
+        assert!(config.is_quiet(at(Weekday::Sat, 12, 0)));
+        assert!(config.is_quiet(at(Weekday::Sun, 9, 0)));
+        assert!(!config.is_quiet(at(Weekday::Fri, 12, 0)));
+        assert!(!config.is_quiet(at(Weekday::Mon, 8, 0)));
+    }
+
+    #[test]
+    fn only_working_days_without_time() {
+        let config = quiet_hours(None, None);
+
+        assert!(!config.is_quiet(at(Weekday::Mon, 3, 0)));
+        assert!(config.is_quiet(at(Weekday::Sat, 12, 0)));
+    }
+}
@@ -0,0 +1,42 @@
+use crate::{
+    anytype::entities::{api_response::AnytypeObject, notification::NotificationObject},
+    config::Route,
+    matrix::client::RoomId,
+};
+
+impl Route {
+    /// Check if the object type and the property values match the route
+    fn matches(&self, notification: &NotificationObject) -> bool {
+        if notification.type_key.as_deref() != Some(self.type_key.as_str()) {
+            return false;
+        }
+
+        match &self.property {
+            Some(property) => AnytypeObject::select_values(&notification.properties, property)
+                .iter()
+                .any(|v| self.values.contains(v)),
+            None => true,
+        }
+    }
+}
+
+/// Get rooms of all matched routes, or the default room if none matches
+pub fn select_rooms(
+    routes: &[Route],
+    default_room: &RoomId,
+    notification: &NotificationObject,
+) -> Vec<RoomId> {
+    let mut rooms: Vec<RoomId> = Vec::new();
+
+    for route in routes.iter().filter(|r| r.matches(notification)) {
+        if !rooms.contains(&route.room_id) {
+            rooms.push(route.room_id.clone());
+        }
+    }
+
+    if rooms.is_empty() {
+        rooms.push(default_room.clone());
+    }
+
+    rooms
+}
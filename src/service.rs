@@ -0,0 +1,681 @@
+use crate::{
+    anytype::{
+        entities::{
+            api_response::ApiResponse,
+            cache::AnytypeCache,
+            notification::{AnytypeToMatrixIdMap, NotificationObject},
+        },
+        parser::get_anytype_to_matrix_map,
+        sentinel::{CACHE_PATH, find_objects_to_notify, load_from_cache, search_notify_objects},
+    },
+    config::{AppConfig, ConfigError},
+    digest::{Digest, DigestState, PersonalDigest, PersonalDigestState, SentRooms},
+    dotenv_vars::{AnytypeVars, MatrixVars},
+    error::{AppError, Context},
+    locale::Locale,
+    matrix::{
+        api::auth::{DeviceId, UserId},
+        client::{Client, RoomId, set_client},
+        direct_rooms::DirectRooms,
+        error::SendError,
+        messages,
+    },
+    queue::{NotificationQueue, QueuedNotification},
+    routing::select_rooms,
+    templates::Templates,
+};
+
+use std::{
+    borrow::Cow,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Utc};
+
+use tokio::time::{self, MissedTickBehavior};
+
+/// Long-living state of the notification service, which is kept in memory between checks
+pub struct Service {
+    config: AppConfig,
+    anytype_env: AnytypeVars,
+    matrix_env: MatrixVars,
+    matrix_client: Client,
+    device_id: DeviceId,
+    user_id: UserId,
+    matrix_id_map: AnytypeToMatrixIdMap,
+    direct_rooms: DirectRooms,
+    templates: Templates,
+}
+
+impl Service {
+    /// Set up the Matrix client and load the Anytype to Matrix id mapping
+    pub async fn new(
+        config: AppConfig,
+        anytype_env: AnytypeVars,
+        matrix_env: MatrixVars,
+    ) -> Result<Self, AppError> {
+        let locale = Locale::new(&config.locale, config.timezone).map_err(ConfigError::from)?;
+        let templates = Templates::load(&config.templates, locale).map_err(ConfigError::from)?;
+
+        let matrix_client = set_client(matrix_env.server.clone()).await?;
+        let me = matrix_client.auth().who_am_i().await?;
+
+        // Get mapping for finding matrix user ids by anytype space member ids
+        let matrix_id_map = get_anytype_to_matrix_map(
+            &anytype_env.url,
+            &anytype_env.token,
+            &config.anytype_to_matrix_id_map_type.0,
+            config.page_size,
+        )
+        .await
+        .context("can not get anytype to matrix id mapping")?;
+
+        let direct_rooms = DirectRooms::load()?;
+
+        Ok(Self {
+            config,
+            anytype_env,
+            matrix_env,
+            matrix_client,
+            device_id: me.device_id,
+            user_id: me.user_id,
+            matrix_id_map,
+            direct_rooms,
+            templates,
+        })
+    }
+
+    /// Run checks every `interval_minutes` until SIGTERM or SIGINT is received.
+    /// The notifications batch that is being sent when the signal comes is finished first.
+    /// Errors that won't go away on the next check stop the service
+    pub async fn run(&mut self) -> Result<(), AppError> {
+        let period = Duration::from_secs(self.config.interval_minutes * 60);
+        let mut ticker = time::interval(period);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        let shutdown = shutdown_signal();
+        tokio::pin!(shutdown);
+
+        loop {
+            tokio::select! {
+                biased;
+                _ = &mut shutdown => {
+                    println!("Shutdown signal received, stopping");
+                    break;
+                }
+                _ = ticker.tick() => {
+                    match self.check().await {
+                        Err(err) if err.is_fatal() => return Err(err),
+                        Err(err) => eprintln!("Error: {err}"),
+                        Ok(()) => {}
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Find objects to notify and send notifications for them, then send the digest if it is time
+    pub async fn check(&mut self) -> Result<(), AppError> {
+        self.refresh_matrix_id_map().await;
+
+        // Digests are sent even if some notifications failed
+        let mut errors = Vec::new();
+
+        if let Err(err) = self.send_notifications().await {
+            errors.push(err);
+        }
+
+        if let Err(err) = self.send_digest().await.context("sending digest failed") {
+            errors.push(err);
+        }
+
+        if let Err(err) = self
+            .send_personal_digests()
+            .await
+            .context("sending personal digests failed")
+        {
+            errors.push(err);
+        }
+
+        if !errors.is_empty() {
+            return Err(AppError::Multiple(errors));
+        }
+
+        Ok(())
+    }
+
+    /// Find objects to notify and send notifications for them.
+    /// Objects are marked as notified in the cache once their notifications are sent to any room,
+    /// the ones not sent anywhere are notified again on the next check. Rooms that failed are
+    /// retried from the queue, except the rooms that rejected the notification
+    async fn send_notifications(&mut self) -> Result<(), AppError> {
+        let found =
+            find_objects_to_notify(&self.anytype_env.url, &self.anytype_env.token, &self.config)
+                .await
+                .context("find_new_objects failed")?;
+
+        // Initial cache is created at the first run, there is nothing to notify yet
+        let Some((objects_to_notify, mut transaction)) = found else {
+            return Ok(());
+        };
+        let objects_to_notify = objects_to_notify.objects;
+
+        if objects_to_notify.is_empty() {
+            println!("No objects to notify");
+        } else {
+            println!("Found {} objects to notify", objects_to_notify.len());
+        }
+
+        // The cache is updated, but the tasks are only listed in the digest
+        if self
+            .config
+            .digest
+            .as_ref()
+            .is_some_and(|d| d.replace_notifications)
+        {
+            if !objects_to_notify.is_empty() {
+                println!("Notifications are replaced by the digest, skipping");
+            }
+            return Ok(transaction.commit().await?);
+        }
+
+        let mut queue = NotificationQueue::load()?;
+
+        let had_queued = !queue.objects.is_empty();
+
+        let now = Utc::now().with_timezone(&self.config.timezone);
+        let quiet_hours = self.config.quiet_hours.as_ref();
+
+        // Notifications from the queue and from this check, the latter are flagged with true
+        let mut objects_to_send: Vec<(QueuedNotification, bool)> = Vec::new();
+
+        let from_check = |notification| {
+            (
+                QueuedNotification {
+                    notification,
+                    rooms: None,
+                },
+                true,
+            )
+        };
+
+        // During the quiet time only urgent notifications are sent, others wait in the queue
+        if quiet_hours.is_some_and(|q| q.is_quiet(now)) {
+            for object in objects_to_notify {
+                if quiet_hours.is_some_and(|q| q.bypasses(&object)) {
+                    objects_to_send.push(from_check(object));
+                } else {
+                    queue.push(object, None);
+                }
+            }
+
+            if !queue.objects.is_empty() {
+                println!(
+                    "Quiet hours, {} notifications are queued",
+                    queue.objects.len()
+                );
+            }
+        } else {
+            objects_to_send.extend(queue.objects.drain(..).map(|o| (o, false)));
+            objects_to_send.extend(objects_to_notify.into_iter().map(from_check));
+        }
+
+        let mut failures = Vec::new();
+        // Failures that won't go away on retry, they are reported once
+        let mut dropped = Vec::new();
+        // The error that stops the service, e.g. the Matrix tokens can't be refreshed
+        let mut fatal_error = None;
+
+        for (queued, from_check) in &objects_to_send {
+            let object = &queued.notification;
+
+            // Direct messages are sent with the first delivery to any room
+            let rooms = match &queued.rooms {
+                Some(rooms) => rooms.clone(),
+                None => select_rooms(&self.config.routes, &self.matrix_env.room_id, object),
+            };
+            let first_delivery = queued.rooms.is_none();
+
+            let failed = self.deliver(object, &rooms, first_delivery).await;
+
+            if failed.is_empty() {
+                continue;
+            }
+
+            // Rooms that rejected the notification (e.g. the bot is not in the room) are not retried
+            let retry_rooms: Vec<RoomId> = failed
+                .iter()
+                .filter(|(_, err)| !err.is_permanent())
+                .map(|(room_id, _)| room_id.clone())
+                .collect();
+
+            if retry_rooms.len() == rooms.len() {
+                if *from_check {
+                    // Not sent anywhere, the object is found again on the next check
+                    transaction.revert(object);
+                } else {
+                    queue.push(object.clone(), queued.rooms.clone());
+                }
+            } else if !retry_rooms.is_empty() {
+                // Only the failed rooms are retried, the others already got the notification
+                queue.push(object.clone(), Some(retry_rooms));
+            }
+
+            for (room_id, err) in failed {
+                let failure = format!(
+                    "\"{}\" ({}) to {}: {err}",
+                    object.name,
+                    object.notification_type.template_key(),
+                    room_id.0
+                );
+
+                if err.is_permanent() {
+                    dropped.push(failure);
+                } else {
+                    failures.push(failure);
+                }
+
+                if err.is_fatal() && fatal_error.is_none() {
+                    fatal_error = Some(err);
+                }
+            }
+        }
+
+        if had_queued || !queue.objects.is_empty() {
+            queue.save()?;
+        }
+
+        transaction.commit().await?;
+
+        if !dropped.is_empty() {
+            eprintln!(
+                "Failed to send {} notifications to rooms, the rooms rejected them and they will not be retried:",
+                dropped.len()
+            );
+            for failure in &dropped {
+                eprintln!("  - {failure}");
+            }
+        }
+
+        if !failures.is_empty() {
+            eprintln!(
+                "Failed to send {} notifications to rooms, they will be retried on the next check:",
+                failures.len()
+            );
+            for failure in &failures {
+                eprintln!("  - {failure}");
+            }
+        }
+
+        if !failures.is_empty() || !dropped.is_empty() {
+            let not_sent = failures.len() + dropped.len();
+            return Err(fatal_error.unwrap_or(SendError::NotSent(not_sent).into()));
+        }
+
+        Ok(())
+    }
+
+    /// Send the notification to the rooms and, with ```direct```, to the assignees if it is
+    /// enabled and the notification got to any room. The rooms that failed are returned
+    async fn deliver(
+        &mut self,
+        object: &NotificationObject,
+        rooms: &[RoomId],
+        direct: bool,
+    ) -> Vec<(RoomId, AppError)> {
+        let mut failed = Vec::new();
+
+        for room_id in rooms {
+            if let Err(err) = messages::send_message(
+                object,
+                &self.matrix_id_map,
+                &self.templates,
+                &self.config,
+                &self.matrix_client,
+                room_id,
+                &self.device_id,
+            )
+            .await
+            .with_context(|| format!("sending notification to {} failed", room_id.0))
+            {
+                failed.push((room_id.clone(), err));
+            }
+        }
+
+        if direct && self.config.direct_messages && failed.len() < rooms.len() {
+            self.send_direct_messages(object).await;
+        }
+
+        failed
+    }
+
+    /// Send the digest to every room with listed tasks if its scheduled time has come.
+    /// Rooms that got it are saved, so only the failed rooms are retried on the next check
+    async fn send_digest(&self) -> Result<(), AppError> {
+        let Some(digest_config) = &self.config.digest else {
+            return Ok(());
+        };
+
+        let mut state = DigestState::load()?;
+
+        // The schedule starts from the first run, the tasks are not listed as new since forever
+        let Some(last_sent) = state.last_sent else {
+            state.last_sent = Some(SystemTime::now());
+            return Ok(state.save()?);
+        };
+
+        let now = Utc::now().with_timezone(&self.config.timezone);
+        let Some(scheduled) = digest_config.schedule.last_scheduled(now) else {
+            return Ok(());
+        };
+
+        if DateTime::<Utc>::from(last_sent) >= scheduled {
+            return Ok(());
+        }
+
+        let Some((current_objects, cached_objects)) = self.load_digest_objects().await? else {
+            return Ok(());
+        };
+
+        let digest = Digest::build(
+            &current_objects,
+            &cached_objects,
+            &self.config,
+            digest_config,
+            last_sent,
+        );
+
+        if digest.is_empty() {
+            println!("No open tasks for the digest");
+        }
+
+        let scheduled = SystemTime::from(scheduled);
+
+        // Rooms that got the digest of an earlier schedule are sent the new one
+        let mut sent_rooms = match state.sent_rooms.take() {
+            Some(sent_rooms) if sent_rooms.scheduled == scheduled => sent_rooms.rooms,
+            _ => Vec::new(),
+        };
+
+        let mut errors = Vec::new();
+
+        for room_id in digest.rooms(&self.config.routes, &self.matrix_env.room_id) {
+            if sent_rooms.contains(&room_id) {
+                continue;
+            }
+
+            let room_digest =
+                digest.for_room(&self.config.routes, &self.matrix_env.room_id, &room_id);
+
+            let result = messages::send_digest(
+                "digest_title",
+                &room_digest.groups(),
+                &self.matrix_id_map,
+                &self.templates,
+                &self.matrix_client,
+                &room_id,
+                &self.device_id,
+            )
+            .await
+            .with_context(|| format!("sending digest to {} failed", room_id.0));
+
+            match result {
+                Ok(()) => sent_rooms.push(room_id),
+                Err(err) => errors.push(err),
+            }
+        }
+
+        if errors.is_empty() {
+            state.last_sent = Some(SystemTime::now());
+        } else {
+            state.sent_rooms = Some(SentRooms {
+                scheduled,
+                rooms: sent_rooms,
+            });
+        }
+
+        state.save()?;
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(AppError::Multiple(errors)),
+        }
+    }
+
+    /// Send the personal digest to every member who enabled it, when its time has come
+    /// in the time zone of the member. Failures are only reported and retried on the next check
+    async fn send_personal_digests(&mut self) -> Result<(), AppError> {
+        let Some(schedule) = self.config.personal_digest.clone() else {
+            return Ok(());
+        };
+
+        let mut state = DigestState::load()?;
+        let now = Utc::now();
+
+        // Members who disabled the digest start from scratch when they enable it again
+        let members_count = state.personal.len();
+        state
+            .personal
+            .retain(|id, _| self.matrix_id_map.personal_digest.contains(id));
+        let forgotten = state.personal.len() != members_count;
+
+        let mut due_members = Vec::new();
+
+        for member_id in &self.matrix_id_map.personal_digest {
+            let timezone = match self.matrix_id_map.timezones.get(member_id) {
+                Some(timezone) => *timezone,
+                None => self.config.timezone,
+            };
+
+            let Some(scheduled) = schedule.last_scheduled(now.with_timezone(&timezone)) else {
+                continue;
+            };
+
+            if state
+                .personal
+                .get(member_id)
+                .is_none_or(|s| DateTime::<Utc>::from(s.last_sent) < scheduled)
+            {
+                due_members.push((member_id.clone(), timezone));
+            }
+        }
+
+        if due_members.is_empty() {
+            if forgotten {
+                state.save()?;
+            }
+            return Ok(());
+        }
+
+        let Some((current_objects, cached_objects)) = self.load_digest_objects().await? else {
+            return Ok(());
+        };
+
+        for (member_id, timezone) in due_members {
+            let previous = state.personal.get(&member_id);
+
+            let digest = PersonalDigest::build(
+                &member_id,
+                previous.map(|s| s.tasks.as_slice()),
+                &current_objects,
+                &cached_objects,
+                &self.config,
+            );
+
+            let personal_state = PersonalDigestState {
+                last_sent: SystemTime::now(),
+                tasks: digest.task_ids(),
+            };
+
+            if digest.is_empty() {
+                state.personal.insert(member_id, personal_state);
+                continue;
+            }
+
+            let Some(matrix_id) = self.matrix_id_map.map.get(&member_id).cloned() else {
+                continue;
+            };
+
+            // Don't send messages to the bot itself
+            if matrix_id == self.user_id.0 {
+                continue;
+            }
+
+            let room_id = match self
+                .matrix_client
+                .room()
+                .get_or_create_direct_room(
+                    &self.user_id,
+                    &UserId(matrix_id.clone()),
+                    &mut self.direct_rooms,
+                )
+                .await
+            {
+                Ok(room_id) => room_id,
+                Err(err) => {
+                    eprintln!("Warn: can not get direct room with {matrix_id}: {err}");
+                    continue;
+                }
+            };
+
+            if let Err(err) = messages::send_digest(
+                "personal_digest_title",
+                &digest.groups(),
+                &self.matrix_id_map,
+                &self.templates.with_timezone(timezone),
+                &self.matrix_client,
+                &room_id,
+                &self.device_id,
+            )
+            .await
+            {
+                eprintln!("Warn: sending personal digest to {matrix_id} failed: {err}");
+                continue;
+            }
+
+            state.personal.insert(member_id, personal_state);
+        }
+
+        Ok(state.save()?)
+    }
+
+    /// Get the checked objects and their cache for digests,
+    /// nothing is tracked before the first check creates the cache
+    async fn load_digest_objects(&self) -> Result<Option<(ApiResponse, AnytypeCache)>, AppError> {
+        if !Path::new(CACHE_PATH).exists() {
+            return Ok(None);
+        }
+
+        let current_objects =
+            search_notify_objects(&self.anytype_env.url, &self.anytype_env.token, &self.config)
+                .await?;
+        let cached_objects = load_from_cache(CACHE_PATH).await?;
+
+        Ok(Some((current_objects, cached_objects)))
+    }
+
+    /// Send a personal copy of the notification to every mapped assignee.
+    /// Failures are only reported, because the notification is already sent to the room
+    async fn send_direct_messages(&mut self, object: &NotificationObject) {
+        for assignee in &object.assignee {
+            let Some(matrix_id) = self.matrix_id_map.map.get(assignee) else {
+                continue;
+            };
+
+            // Don't send messages to the bot itself
+            if *matrix_id == self.user_id.0 {
+                continue;
+            }
+
+            let user_id = UserId(matrix_id.clone());
+
+            let room_id = match self
+                .matrix_client
+                .room()
+                .get_or_create_direct_room(&self.user_id, &user_id, &mut self.direct_rooms)
+                .await
+            {
+                Ok(room_id) => room_id,
+                Err(err) => {
+                    eprintln!("Warn: can not get direct room with {matrix_id}: {err}");
+                    continue;
+                }
+            };
+
+            // Show dates in the personal time zone of the member if it is set
+            let templates = match self.matrix_id_map.timezones.get(assignee) {
+                Some(timezone) => Cow::Owned(self.templates.with_timezone(*timezone)),
+                None => Cow::Borrowed(&self.templates),
+            };
+
+            if let Err(err) = messages::send_message(
+                object,
+                &self.matrix_id_map,
+                &templates,
+                &self.config,
+                &self.matrix_client,
+                &room_id,
+                &self.device_id,
+            )
+            .await
+            {
+                eprintln!("Warn: sending direct notification to {matrix_id} failed: {err}");
+            }
+        }
+    }
+
+    /// Reload the Anytype to Matrix id mapping. The previous mapping is kept if it fails
+    async fn refresh_matrix_id_map(&mut self) {
+        match get_anytype_to_matrix_map(
+            &self.anytype_env.url,
+            &self.anytype_env.token,
+            &self.config.anytype_to_matrix_id_map_type.0,
+            self.config.page_size,
+        )
+        .await
+        {
+            Ok(map) => self.matrix_id_map = map,
+            Err(err) => {
+                eprintln!(
+                    "Warn: can not refresh anytype to matrix id mapping, using previous: {err}"
+                )
+            }
+        }
+    }
+}
+
+/// Wait for SIGINT (Ctrl+C) or SIGTERM (docker stop)
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            eprintln!("Error: can not listen for SIGINT: {err}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(err) => {
+                eprintln!("Error: can not listen for SIGTERM: {err}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
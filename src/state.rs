@@ -0,0 +1,121 @@
+use serde::{Serialize, de::DeserializeOwned};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+use thiserror::Error;
+
+/// Errors of the files in assets/ with the state kept between restarts
+#[derive(Debug, Error)]
+pub enum CacheError {
+    #[error("can not read {path}: {source}")]
+    Read { path: String, source: io::Error },
+    #[error("can not write {path}: {source}")]
+    Write { path: String, source: io::Error },
+    #[error("invalid data in {path}: {source}")]
+    Invalid {
+        path: String,
+        source: serde_json::Error,
+    },
+}
+
+/// Read the file contents
+pub fn read_file(path: &str) -> Result<String, CacheError> {
+    fs::read_to_string(path).map_err(|source| CacheError::Read {
+        path: path.to_string(),
+        source,
+    })
+}
+
+/// Create the file that only its owner can read and write
+#[cfg(unix)]
+fn create_private_file(path: &str) -> io::Result<File> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+
+    // The mode is only applied to new files
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    Ok(file)
+}
+
+#[cfg(not(unix))]
+fn create_private_file(path: &str) -> io::Result<File> {
+    File::create(path)
+}
+
+/// Write the contents to a temporary file and replace the file with it,
+/// so the file is never left half-written
+fn replace_file(path: &str, contents: &[u8], private: bool) -> io::Result<()> {
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let temp_path = format!("{path}.tmp");
+
+    let mut file = match private {
+        true => create_private_file(&temp_path)?,
+        false => OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&temp_path)?,
+    };
+    file.write_all(contents)?;
+    file.sync_all()?;
+
+    fs::rename(&temp_path, path)
+}
+
+/// Write the file, its directory is created if it doesn't exist
+pub fn write_file(path: &str, contents: &[u8]) -> Result<(), CacheError> {
+    replace_file(path, contents, false).map_err(|source| CacheError::Write {
+        path: path.to_string(),
+        source,
+    })
+}
+
+/// Write the file that only its owner can read and write, e.g. with secrets
+pub fn write_private_file(path: &str, contents: &[u8]) -> Result<(), CacheError> {
+    replace_file(path, contents, true).map_err(|source| CacheError::Write {
+        path: path.to_string(),
+        source,
+    })
+}
+
+/// Remove the file if it exists
+pub fn remove_file(path: &str) -> Result<(), CacheError> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(CacheError::Write {
+            path: path.to_string(),
+            source: err,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Load the state from the JSON file
+pub fn read_json<T: DeserializeOwned>(path: &str) -> Result<T, CacheError> {
+    let data = read_file(path)?;
+
+    serde_json::from_str(&data).map_err(|source| CacheError::Invalid {
+        path: path.to_string(),
+        source,
+    })
+}
+
+/// Save the state to the JSON file
+pub fn write_json<T: Serialize>(path: &str, value: &T) -> Result<(), CacheError> {
+    let data = serde_json::to_vec_pretty(value).map_err(|source| CacheError::Invalid {
+        path: path.to_string(),
+        source,
+    })?;
+
+    write_file(path, &data)
+}
@@ -0,0 +1,498 @@
+use crate::{anytype::entities::notification::NotificationType, locale::Locale};
+
+use chrono_tz::Tz;
+use std::{collections::HashMap, fs, io};
+use thiserror::Error;
+
+/// Keys of the templates for every notification type
+pub const TEMPLATE_KEYS: [&str; 9] = [
+    "new",
+    "unassigned",
+    "upcoming_deadline",
+    "assignee_changed",
+    "deadline_changed",
+    "status_changed",
+    "archived",
+    "deleted",
+    "overdue",
+];
+
+/// Names of the placeholders, which can be used in templates.
+/// Object properties are available as {{property.<key>}}
+pub const PLACEHOLDERS: [&str; 20] = [
+    "id",
+    "space_id",
+    "type_key",
+    "name",
+    "snippet",
+    "creation_date",
+    "due_date",
+    "assignee",
+    "proposed_by",
+    "link",
+    "web_link",
+    "added",
+    "removed",
+    "old_due_date",
+    "new_due_date",
+    "old_status",
+    "new_status",
+    "done",
+    "missed",
+    "escalate",
+];
+
+/// Built-in templates of the locale from the "templates/<locale>" directory
+macro_rules! builtin_template {
+    ($locale:literal, $key:expr) => {
+        match $key {
+            "new" => Some(include_str!(concat!("../templates/", $locale, "/new.txt"))),
+            "unassigned" => Some(include_str!(concat!(
+                "../templates/",
+                $locale,
+                "/unassigned.txt"
+            ))),
+            "upcoming_deadline" => Some(include_str!(concat!(
+                "../templates/",
+                $locale,
+                "/upcoming_deadline.txt"
+            ))),
+            "assignee_changed" => Some(include_str!(concat!(
+                "../templates/",
+                $locale,
+                "/assignee_changed.txt"
+            ))),
+            "deadline_changed" => Some(include_str!(concat!(
+                "../templates/",
+                $locale,
+                "/deadline_changed.txt"
+            ))),
+            "status_changed" => Some(include_str!(concat!(
+                "../templates/",
+                $locale,
+                "/status_changed.txt"
+            ))),
+            "archived" => Some(include_str!(concat!(
+                "../templates/",
+                $locale,
+                "/archived.txt"
+            ))),
+            "deleted" => Some(include_str!(concat!(
+                "../templates/",
+                $locale,
+                "/deleted.txt"
+            ))),
+            "overdue" => Some(include_str!(concat!(
+                "../templates/",
+                $locale,
+                "/overdue.txt"
+            ))),
+            _ => None,
+        }
+    };
+}
+
+/// Default template used when a template file is not set in config.toml
+fn default_template(locale: &str, key: &str) -> Option<&'static str> {
+    match locale {
+        "ru" => builtin_template!("ru", key),
+        "en" => builtin_template!("en", key),
+        _ => None,
+    }
+}
+
+impl NotificationType {
+    /// Key of the template for this notification type
+    pub fn template_key(&self) -> &'static str {
+        match self {
+            NotificationType::New => "new",
+            NotificationType::Unassigned => "unassigned",
+            NotificationType::UpcomingDeadline => "upcoming_deadline",
+            NotificationType::AssigneeChanged { .. } => "assignee_changed",
+            NotificationType::DeadlineChanged { .. } => "deadline_changed",
+            NotificationType::StatusChanged { .. } => "status_changed",
+            NotificationType::Archived => "archived",
+            NotificationType::Deleted => "deleted",
+            NotificationType::Overdue { .. } => "overdue",
+        }
+    }
+}
+
+/// Errors of parsing and loading the templates
+#[derive(Debug, Error)]
+pub enum TemplateError {
+    #[error("\"{{{{\" is not closed with \"}}}}\"")]
+    UnclosedTag,
+    #[error("{{{{#if {0}}}}} is not closed")]
+    UnclosedIf(String),
+    #[error("{{{{else}}}} without {{{{#if}}}}")]
+    UnexpectedElse,
+    #[error("{{{{/if}}}} without {{{{#if}}}}")]
+    UnexpectedEndIf,
+    #[error("empty placeholder {{{{}}}}")]
+    EmptyPlaceholder,
+    #[error("unknown placeholder \"{0}\"")]
+    UnknownPlaceholder(String),
+    #[error("unknown template \"{0}\", expected one of: {keys}", keys = TEMPLATE_KEYS.join(", "))]
+    UnknownKey(String),
+    #[error("can not read template \"{key}\" from {path}: {source}")]
+    Read {
+        key: &'static str,
+        path: String,
+        source: io::Error,
+    },
+    #[error("invalid template \"{key}\" in {path}: {source}")]
+    Invalid {
+        key: &'static str,
+        path: String,
+        source: Box<TemplateError>,
+    },
+    #[error("no default template \"{0}\"")]
+    MissingDefault(&'static str),
+    #[error("invalid default template \"{key}\" for locale \"{locale}\": {source}")]
+    InvalidDefault {
+        key: &'static str,
+        locale: &'static str,
+        source: Box<TemplateError>,
+    },
+}
+
+/// Part of a parsed template
+#[derive(Debug, Clone)]
+pub enum Node {
+    Text(String),
+    // "**" switches bold text on and off
+    Bold,
+    // {{name}} or {{name:label}}
+    Placeholder {
+        name: String,
+        label: Option<String>,
+    },
+    // {{#if name}}...{{else}}...{{/if}}
+    If {
+        name: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Template {
+    pub nodes: Vec<Node>,
+}
+
+impl Template {
+    /// Parse the template text
+    pub fn parse(text: &str) -> Result<Self, TemplateError> {
+        // Trailing line break of the file is not a part of the message
+        let text = text.strip_suffix('\n').unwrap_or(text);
+        let text = text.strip_suffix('\r').unwrap_or(text);
+
+        let mut rest = text;
+        let (nodes, end) = parse_nodes(&mut rest)?;
+
+        match end {
+            BlockEnd::Text => {
+                check_placeholders(&nodes)?;
+                Ok(Self { nodes })
+            }
+            BlockEnd::Else => Err(TemplateError::UnexpectedElse),
+            BlockEnd::EndIf => Err(TemplateError::UnexpectedEndIf),
+        }
+    }
+}
+
+/// What stopped the parsing of nodes
+enum BlockEnd {
+    Text,
+    Else,
+    EndIf,
+}
+
+/// Parse nodes until the end of the text or until {{else}} or {{/if}}
+fn parse_nodes(rest: &mut &str) -> Result<(Vec<Node>, BlockEnd), TemplateError> {
+    let mut nodes = Vec::new();
+
+    loop {
+        let Some(start) = rest.find("{{") else {
+            push_text(&mut nodes, rest);
+            *rest = "";
+            return Ok((nodes, BlockEnd::Text));
+        };
+
+        push_text(&mut nodes, &rest[..start]);
+
+        let Some(end) = rest[start..].find("}}") else {
+            return Err(TemplateError::UnclosedTag);
+        };
+
+        let tag = rest[start + 2..start + end].trim().to_string();
+        *rest = &rest[start + end + 2..];
+
+        if tag == "else" {
+            return Ok((nodes, BlockEnd::Else));
+        }
+
+        if tag == "/if" {
+            return Ok((nodes, BlockEnd::EndIf));
+        }
+
+        if let Some(name) = tag.strip_prefix("#if ") {
+            let name = name.trim().to_string();
+
+            let (then, end) = parse_nodes(rest)?;
+            let otherwise = match end {
+                BlockEnd::EndIf => Vec::new(),
+                BlockEnd::Else => match parse_nodes(rest)? {
+                    (otherwise, BlockEnd::EndIf) => otherwise,
+                    _ => return Err(TemplateError::UnclosedIf(name)),
+                },
+                BlockEnd::Text => return Err(TemplateError::UnclosedIf(name)),
+            };
+
+            nodes.push(Node::If {
+                name,
+                then,
+                otherwise,
+            });
+            continue;
+        }
+
+        let (name, label) = match tag.split_once(':') {
+            Some((name, label)) => (name.trim().to_string(), Some(label.trim().to_string())),
+            None => (tag, None),
+        };
+
+        if name.is_empty() {
+            return Err(TemplateError::EmptyPlaceholder);
+        }
+
+        nodes.push(Node::Placeholder { name, label });
+    }
+}
+
+/// Check that all used placeholders exist
+fn check_placeholders(nodes: &[Node]) -> Result<(), TemplateError> {
+    for node in nodes {
+        let name = match node {
+            Node::Placeholder { name, .. } => name,
+            Node::If {
+                name,
+                then,
+                otherwise,
+            } => {
+                check_placeholders(then)?;
+                check_placeholders(otherwise)?;
+                name
+            }
+            _ => continue,
+        };
+
+        if !PLACEHOLDERS.contains(&name.as_str()) && !name.starts_with("property.") {
+            return Err(TemplateError::UnknownPlaceholder(name.clone()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Add text with "**" converted to bold switches
+fn push_text(nodes: &mut Vec<Node>, text: &str) {
+    for (i, part) in text.split("**").enumerate() {
+        if i > 0 {
+            nodes.push(Node::Bold);
+        }
+
+        if !part.is_empty() {
+            nodes.push(Node::Text(part.to_string()));
+        }
+    }
+}
+
+/// Templates for all notification types and the locale for their values
+#[derive(Debug, Clone)]
+pub struct Templates {
+    templates: HashMap<String, Template>,
+    pub locale: Locale,
+}
+
+impl Templates {
+    /// Load templates from the files set in config.toml,
+    /// default templates of the locale are used for others
+    pub fn load(files: &HashMap<String, String>, locale: Locale) -> Result<Self, TemplateError> {
+        if let Some(key) = files.keys().find(|k| !TEMPLATE_KEYS.contains(&k.as_str())) {
+            return Err(TemplateError::UnknownKey(key.clone()));
+        }
+
+        let mut templates = HashMap::new();
+
+        for key in TEMPLATE_KEYS {
+            let template = match files.get(key) {
+                Some(path) => {
+                    let text = fs::read_to_string(path).map_err(|source| TemplateError::Read {
+                        key,
+                        path: path.clone(),
+                        source,
+                    })?;
+                    Template::parse(&text).map_err(|e| TemplateError::Invalid {
+                        key,
+                        path: path.clone(),
+                        source: Box::new(e),
+                    })?
+                }
+                None => {
+                    let (name, text) = locale
+                        .chain()
+                        .iter()
+                        .find_map(|l| default_template(l, key).map(|t| (*l, t)))
+                        .ok_or(TemplateError::MissingDefault(key))?;
+
+                    Template::parse(text).map_err(|e| TemplateError::InvalidDefault {
+                        key,
+                        locale: name,
+                        source: Box::new(e),
+                    })?
+                }
+            };
+
+            templates.insert(key.to_string(), template);
+        }
+
+        Ok(Self { templates, locale })
+    }
+
+    /// Same templates with dates shown in another time zone
+    pub fn with_timezone(&self, timezone: Tz) -> Self {
+        Self {
+            templates: self.templates.clone(),
+            locale: self.locale.with_timezone(timezone),
+        }
+    }
+
+    /// Get the template for the notification type
+    pub fn get(&self, notification_type: &NotificationType) -> Option<&Template> {
+        self.templates.get(notification_type.template_key())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(node: &Node) -> &str {
+        match node {
+            Node::Text(text) => text,
+            other => panic!("expected text, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_nested_if_with_else() {
+        let template = Template::parse(
+            "{{#if link}}{{#if web_link}}both{{else}}app{{/if}}{{else}}none{{/if}}\n",
+        )
+        .unwrap();
+
+        let [
+            Node::If {
+                name,
+                then,
+                otherwise,
+            },
+        ] = template.nodes.as_slice()
+        else {
+            panic!("expected one if block, got {:?}", template.nodes);
+        };
+        assert_eq!(name, "link");
+        assert_eq!(otherwise.len(), 1);
+        assert_eq!(text(&otherwise[0]), "none");
+
+        let [
+            Node::If {
+                name,
+                then,
+                otherwise,
+            },
+        ] = then.as_slice()
+        else {
+            panic!("expected nested if block, got {then:?}");
+        };
+        assert_eq!(name, "web_link");
+        assert_eq!(text(&then[0]), "both");
+        assert_eq!(text(&otherwise[0]), "app");
+    }
+
+    #[test]
+    fn parses_placeholder_label() {
+        let template = Template::parse("{{ link : Open }}").unwrap();
+
+        let [Node::Placeholder { name, label }] = template.nodes.as_slice() else {
+            panic!("expected placeholder, got {:?}", template.nodes);
+        };
+        assert_eq!(name, "link");
+        assert_eq!(label.as_deref(), Some("Open"));
+    }
+
+    #[test]
+    fn toggles_bold() {
+        let template = Template::parse("a **b** c**").unwrap();
+
+        let kinds: Vec<&str> = template
+            .nodes
+            .iter()
+            .map(|node| match node {
+                Node::Text(text) => text.as_str(),
+                Node::Bold => "**",
+                _ => panic!("unexpected node {node:?}"),
+            })
+            .collect();
+        assert_eq!(kinds, ["a ", "**", "b", "**", " c", "**"]);
+    }
+
+    #[test]
+    fn rejects_unclosed_blocks() {
+        assert!(matches!(
+            Template::parse("{{#if link}}text"),
+            Err(TemplateError::UnclosedIf(name)) if name == "link"
+        ));
+        assert!(matches!(
+            Template::parse("{{#if link}}a{{else}}b"),
+            Err(TemplateError::UnclosedIf(_))
+        ));
+        assert!(matches!(
+            Template::parse("{{name"),
+            Err(TemplateError::UnclosedTag)
+        ));
+        assert!(matches!(
+            Template::parse("a{{else}}b"),
+            Err(TemplateError::UnexpectedElse)
+        ));
+        assert!(matches!(
+            Template::parse("a{{/if}}"),
+            Err(TemplateError::UnexpectedEndIf)
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_placeholders() {
+        assert!(matches!(
+            Template::parse("{{#if link}}{{nmae}}{{/if}}"),
+            Err(TemplateError::UnknownPlaceholder(name)) if name == "nmae"
+        ));
+        assert!(matches!(
+            Template::parse("{{}}"),
+            Err(TemplateError::EmptyPlaceholder)
+        ));
+        assert!(Template::parse("{{property.priority}}").is_ok());
+    }
+
+    #[test]
+    fn builtin_templates_are_valid() {
+        for locale in crate::locale::LOCALES {
+            for key in TEMPLATE_KEYS {
+                let text = default_template(locale, key).unwrap();
+                assert!(Template::parse(text).is_ok(), "{locale}/{key}");
+            }
+        }
+    }
+}